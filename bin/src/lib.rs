@@ -0,0 +1,3 @@
+//! Code shared by the bins.
+
+pub mod local;
@@ -0,0 +1,212 @@
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use types::compact;
+use types::eth::BlockTrace;
+use zkevm::fixture::{FixtureManifest, MANIFEST_FILE};
+use zkevm::utils::try_get_block_trace_from_file;
+
+/// File grouping the traces of a dir into batches, a list of `BatchRange`.
+const BATCHES_FILE: &str = "batches.json";
+
+#[derive(Debug, Deserialize)]
+struct BatchRange {
+    index: i64,
+    start_block_number: u64,
+    end_block_number: u64,
+}
+
+/// Traces of batches stored locally. `path` is either
+/// - a dir of recorded fixtures, grouped by the batch of the manifest, one batch per block
+///   recorded without a batch,
+/// - a dir with one sub dir of trace files per batch, named by the batch index,
+/// - a dir of trace files named `<block number>.json`, grouped by `batches.json` if it exists,
+///   one batch per block otherwise, or
+/// - an archive file with one batch per line, each a JSON array of traces, gzip compressed if
+///   the name ends with `.gz`. Batch indexes count lines from 1.
+pub enum LocalTraces {
+    Dirs(Vec<(i64, PathBuf)>),
+    Files(Vec<(i64, Vec<PathBuf>)>),
+    Archive(PathBuf),
+}
+
+impl LocalTraces {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        if path.is_file() {
+            return Ok(Self::Archive(path.to_path_buf()));
+        }
+        if let Some(manifest) = FixtureManifest::load(path)? {
+            return Ok(Self::Files(manifest_batches(path, &manifest)));
+        }
+
+        let mut dirs = vec![];
+        let mut files = vec![];
+        for entry in fs::read_dir(path).with_context(|| format!("cannot read {path:?}"))? {
+            let path = entry?.path();
+            if path.is_dir() {
+                let index = parse_number(&path)
+                    .with_context(|| format!("batch dir {path:?} is not a batch index"))?;
+                dirs.push((index as i64, path));
+            } else if is_trace_file(&path) {
+                files.push(path);
+            }
+        }
+        if !dirs.is_empty() {
+            dirs.sort();
+            return Ok(Self::Dirs(dirs));
+        }
+
+        let files = numbered_files(files)?;
+
+        let batches_path = path.join(BATCHES_FILE);
+        let batches = if batches_path.exists() {
+            let ranges: Vec<BatchRange> = serde_json::from_reader(File::open(&batches_path)?)?;
+            ranges
+                .into_iter()
+                .map(|range| {
+                    let paths = files
+                        .iter()
+                        .filter(|(n, _)| {
+                            (range.start_block_number..=range.end_block_number).contains(n)
+                        })
+                        .map(|(_, path)| path.clone())
+                        .collect::<Vec<_>>();
+                    let expected = range.end_block_number + 1 - range.start_block_number;
+                    if paths.len() as u64 != expected {
+                        bail!(
+                            "batch {} expects {} blocks, {} found",
+                            range.index,
+                            expected,
+                            paths.len()
+                        );
+                    }
+                    Ok((range.index, paths))
+                })
+                .collect::<Result<Vec<_>>>()?
+        } else {
+            files
+                .into_iter()
+                .enumerate()
+                .map(|(idx, (_, path))| (idx as i64 + 1, vec![path]))
+                .collect()
+        };
+        Ok(Self::Files(batches))
+    }
+
+    /// iterate the batches in order, loading the traces of each batch on demand.
+    pub fn into_batches(self) -> Result<Batches> {
+        Ok(match self {
+            Self::Dirs(dirs) => Box::new(dirs.into_iter().map(|(index, dir)| {
+                let traces = fs::read_dir(dir)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|entries| {
+                        let mut paths =
+                            entries.map(|e| Ok(e?.path())).collect::<Result<Vec<_>>>()?;
+                        paths.retain(|p| is_trace_file(p));
+                        load_sorted(paths)
+                    });
+                (index, traces)
+            })),
+            Self::Files(batches) => Box::new(
+                batches
+                    .into_iter()
+                    .map(|(index, paths)| (index, load_sorted(paths))),
+            ),
+            Self::Archive(path) => {
+                let fd = File::open(&path)?;
+                let reader: Box<dyn Read + Send> = if path.extension().map_or(false, |e| e == "gz")
+                {
+                    Box::new(GzDecoder::new(fd))
+                } else {
+                    Box::new(fd)
+                };
+                Box::new(
+                    BufReader::new(reader)
+                        .lines()
+                        .filter(|line| line.as_ref().map_or(true, |l| !l.trim().is_empty()))
+                        .enumerate()
+                        .map(|(idx, line)| {
+                            let traces = line
+                                .map_err(anyhow::Error::from)
+                                .and_then(|l| Ok(serde_json::from_str(&l)?));
+                            (idx as i64 + 1, traces)
+                        }),
+                )
+            }
+        })
+    }
+}
+
+fn manifest_batches(dir: &Path, manifest: &FixtureManifest) -> Vec<(i64, Vec<PathBuf>)> {
+    let mut batches: BTreeMap<i64, Vec<PathBuf>> = BTreeMap::new();
+    // blocks recorded without a batch come after the batches, one each
+    let mut next_index = manifest.fixtures.iter().filter_map(|f| f.batch).max();
+    for fixture in &manifest.fixtures {
+        let index = fixture.batch.unwrap_or_else(|| {
+            let index = next_index.map_or(1, |i| i + 1);
+            next_index = Some(index);
+            index
+        });
+        batches
+            .entry(index)
+            .or_default()
+            .push(dir.join(&fixture.path));
+    }
+    batches.into_iter().collect()
+}
+
+/// batch index and traces, in batch order.
+pub type Batches = Box<dyn Iterator<Item = (i64, Result<Vec<BlockTrace>>)> + Send>;
+
+/// return the trace files named `<block number>.json` in the dir by block number.
+pub fn block_files<P: AsRef<Path>>(dir: P) -> Result<BTreeMap<u64, PathBuf>> {
+    let mut paths = vec![];
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if is_trace_file(&path) {
+            paths.push(path);
+        }
+    }
+    Ok(numbered_files(paths)?.into_iter().collect())
+}
+
+fn numbered_files(paths: Vec<PathBuf>) -> Result<Vec<(u64, PathBuf)>> {
+    let mut files = paths
+        .into_iter()
+        .map(|path| {
+            let number = parse_number(&path)
+                .with_context(|| format!("trace file {path:?} is not named by block"))?;
+            Ok((number, path))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    files.sort();
+    Ok(files)
+}
+
+fn is_trace_file(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .map_or(false, |ext| ext == "json" || ext == compact::EXTENSION)
+        && path
+            .file_name()
+            .map_or(false, |name| name != BATCHES_FILE && name != MANIFEST_FILE)
+}
+
+fn parse_number(path: &Path) -> Option<u64> {
+    path.file_stem()?.to_str()?.parse().ok()
+}
+
+fn load_sorted(paths: Vec<PathBuf>) -> Result<Vec<BlockTrace>> {
+    let mut traces = paths
+        .into_iter()
+        .map(try_get_block_trace_from_file)
+        .collect::<Result<Vec<_>>>()?;
+    traces.sort_by_key(|t| t.header.number);
+    Ok(traces)
+}
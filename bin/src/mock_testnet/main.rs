@@ -0,0 +1,256 @@
+mod progress;
+mod source;
+
+use anyhow::{anyhow, bail, Result};
+use itertools::Itertools;
+use progress::{BatchResult, Progress};
+use source::{KromaRpcSource, LocalSource, MockRpcSource, OutputRootBatches, TraceSource};
+use std::env;
+use std::fs;
+use std::path::Path;
+use types::eth::BlockTrace;
+use zkevm::circuit::{SuperCircuit, DEGREE, SUB_CIRCUIT_NAMES, WITNESS_CACHE, ZKEVM_CIRCUITS_REV};
+use zkevm::fixture::FixtureRecorder;
+use zkevm::mock_report::{MockProveFailed, MockProveReport};
+use zkevm::prover::Prover;
+
+const DEFAULT_BEGIN_BATCH: i64 = 1;
+const DEFAULT_END_BATCH: i64 = i64::MAX;
+const DEFAULT_REPORT_DIR: &str = "mock_reports";
+const MAX_LOAD_FAILURES: usize = 8;
+const DEFAULT_MOCK_RPC_ADDR: &str = "127.0.0.1:0";
+/// blocks between two output roots of the L2 output oracle.
+const DEFAULT_SUBMISSION_INTERVAL: u64 = 1800;
+
+#[tokio::main]
+async fn main() {
+    dotenv::dotenv().ok();
+    env_logger::init();
+    // the rows of a batch are counted before it is mock proved, with the same witness block
+    WITNESS_CACHE.opt_in(2);
+
+    log::info!("mock-testnet: begin");
+
+    let setting = Setting::new();
+    log::info!("mock-testnet: {setting:?}");
+
+    let mut progress = Progress::load(setting.progress_file.as_deref(), setting.progress_key())
+        .expect("mock-testnet: failed to load progress");
+    let mut source = setting
+        .trace_source()
+        .expect("mock-testnet: failed to initialize trace source");
+    let recorder = setting
+        .record_dir
+        .as_ref()
+        .map(|dir| FixtureRecorder::new(dir, setting.record_strip.clone()))
+        .transpose()
+        .expect("mock-testnet: failed to initialize trace recorder");
+
+    // not recorded in the progress, so that they are retried on resume
+    let mut load_failures = vec![];
+    for i in setting.begin_batch..=setting.end_batch {
+        if progress.is_done(i) {
+            continue;
+        }
+        log::info!("mock-testnet: requesting block traces of batch {i}");
+
+        match source.batch(i).await {
+            Ok(Some(block_traces)) => {
+                if let Some(recorder) = &recorder {
+                    record_traces(recorder, i, &block_traces);
+                }
+                progress.record(run_batch(&setting, i, &block_traces))
+            }
+            Ok(None) => {
+                log::info!("mock-testnet: finished to prove at batch-{i}");
+                break;
+            }
+            Err(e) => {
+                log::error!("mock-testnet: failed to get traces of batch-{i}: {e:?}");
+                load_failures.push(i);
+                if load_failures.len() >= MAX_LOAD_FAILURES {
+                    log::error!("mock-testnet: too many failures to get traces, stop");
+                    break;
+                }
+            }
+        }
+    }
+
+    log::info!("mock-testnet: summary\n{}", progress.summary());
+    if !load_failures.is_empty() {
+        log::error!("mock-testnet: failed to get traces of batches {load_failures:?}");
+    }
+    log::info!("move-testnet: end");
+}
+
+/// Count the rows of the batch, and mock prove it unless `ROWS_ONLY`.
+fn run_batch(setting: &Setting, i: i64, block_traces: &[BlockTrace]) -> BatchResult {
+    let mut result = BatchResult {
+        index: i,
+        first_block: block_traces
+            .first()
+            .and_then(|b| b.header.number.map(|n| n.as_u64())),
+        last_block: block_traces
+            .last()
+            .and_then(|b| b.header.number.map(|n| n.as_u64())),
+        blocks: block_traces.len(),
+        txs: block_traces.iter().map(|b| b.transactions.len()).sum(),
+        gas: block_traces
+            .iter()
+            .map(|b| b.header.gas_used.as_u64())
+            .sum(),
+        rows: vec![],
+        passed: false,
+        error: None,
+    };
+
+    let outcome = (|| {
+        let rows = WITNESS_CACHE.row_usage(block_traces)?;
+        log::info!(
+            "rows of batch {}(block range {:?} to {:?}):",
+            i,
+            result.first_block,
+            result.last_block,
+        );
+        for (c, r) in SUB_CIRCUIT_NAMES.iter().zip_eq(rows.iter()) {
+            log::info!("rows of {}: {}", c, r);
+        }
+        result.rows = rows;
+        log::info!(
+            "final rows of batch {}: row {}, gas {}, gas/row {:.2}",
+            i,
+            result.max_rows(),
+            result.gas,
+            result.gas as f64 / result.max_rows() as f64
+        );
+        if setting.rows_only {
+            Ok(())
+        } else {
+            Prover::mock_prove_target_circuit_batch::<SuperCircuit>(block_traces)
+        }
+    })();
+    match outcome {
+        Ok(_) => {
+            log::info!("mock-testnet: succeeded to prove batch-{i}");
+            result.passed = true;
+        }
+        Err(err) => {
+            log::error!("mock-testnet: failed to prove batch-{i}:\n{err:?}");
+            if let Some(MockProveFailed(report)) = err.downcast_ref::<MockProveFailed>() {
+                write_report(setting, i, report);
+            }
+            result.error = Some(err.to_string());
+        }
+    }
+    result
+}
+
+/// Save the traces of the batch as fixtures, a failure does not stop the run.
+fn record_traces(recorder: &FixtureRecorder, batch_index: i64, block_traces: &[BlockTrace]) {
+    for block_trace in block_traces {
+        if let Err(e) = recorder.record(block_trace, Some(batch_index)) {
+            log::error!("mock-testnet: failed to record trace of batch-{batch_index}: {e:?}");
+        }
+    }
+}
+
+/// Write the mock prove report of a failed batch to `<REPORT_DIR>/batch_<index>.json`.
+fn write_report(setting: &Setting, batch_index: i64, report: &MockProveReport) {
+    let path = Path::new(&setting.report_dir).join(format!("batch_{batch_index}.json"));
+    let result = fs::create_dir_all(&setting.report_dir)
+        .map_err(anyhow::Error::from)
+        .and_then(|_| report.write(&path));
+    match result {
+        Ok(_) => log::info!("mock-testnet: wrote report of batch-{batch_index} to {path:?}"),
+        Err(e) => log::error!("mock-testnet: failed to write report of batch-{batch_index}: {e}"),
+    }
+}
+
+#[derive(Debug)]
+struct Setting {
+    trace_source: String,
+    begin_batch: i64,
+    end_batch: i64,
+    l2geth_api_url: Option<String>,
+    trace_dir: Option<String>,
+    mock_rpc_addr: String,
+    batches: OutputRootBatches,
+    history_hashes: bool,
+    rows_only: bool,
+    report_dir: String,
+    progress_file: Option<String>,
+    record_dir: Option<String>,
+    record_strip: Vec<String>,
+}
+
+impl Setting {
+    pub fn new() -> Self {
+        let non_empty_var = |key: &str| env::var(key).ok().filter(|v| !v.is_empty());
+        let parsed_var = |key: &str| non_empty_var(key).and_then(|v| v.parse().ok());
+
+        let trace_dir = non_empty_var("TRACE_DIR");
+        let trace_source = non_empty_var("TRACE_SOURCE").unwrap_or_else(|| {
+            if trace_dir.is_some() {
+                "local"
+            } else {
+                "kroma"
+            }
+            .to_string()
+        });
+
+        Self {
+            trace_source,
+            begin_batch: parsed_var("PROVE_BEGIN_BATCH").unwrap_or(DEFAULT_BEGIN_BATCH),
+            end_batch: parsed_var("PROVE_END_BATCH").unwrap_or(DEFAULT_END_BATCH),
+            l2geth_api_url: non_empty_var("L2GETH_API_URL"),
+            trace_dir,
+            mock_rpc_addr: non_empty_var("MOCK_RPC_ADDR")
+                .unwrap_or_else(|| DEFAULT_MOCK_RPC_ADDR.to_string()),
+            batches: OutputRootBatches {
+                starting_block_number: parsed_var("L2OO_STARTING_BLOCK_NUMBER").unwrap_or(0),
+                submission_interval: parsed_var("L2OO_SUBMISSION_INTERVAL")
+                    .unwrap_or(DEFAULT_SUBMISSION_INTERVAL),
+            },
+            history_hashes: parsed_var("FETCH_HISTORY_HASHES").unwrap_or(false),
+            rows_only: parsed_var("ROWS_ONLY").unwrap_or(true),
+            report_dir: non_empty_var("REPORT_DIR")
+                .unwrap_or_else(|| DEFAULT_REPORT_DIR.to_string()),
+            progress_file: non_empty_var("PROGRESS_FILE"),
+            record_dir: non_empty_var("RECORD_DIR"),
+            record_strip: non_empty_var("RECORD_STRIP")
+                .map(|v| v.split(',').map(|f| f.trim().to_string()).collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// results of a run are only resumed by a run of the same circuits and mode.
+    fn progress_key(&self) -> String {
+        let mode = if self.rows_only { "rows" } else { "mock_prove" };
+        format!("{ZKEVM_CIRCUITS_REV}:{}:{mode}", *DEGREE)
+    }
+
+    fn trace_source(&self) -> Result<Box<dyn TraceSource>> {
+        let trace_dir = || {
+            self.trace_dir
+                .as_deref()
+                .ok_or_else(|| anyhow!("Must set env TRACE_DIR for {} source", self.trace_source))
+        };
+        Ok(match self.trace_source.as_str() {
+            "kroma" => {
+                let url = self
+                    .l2geth_api_url
+                    .as_deref()
+                    .ok_or_else(|| anyhow!("Must set env L2GETH_API_URL for kroma source"))?;
+                Box::new(KromaRpcSource::new(url, self.batches, self.history_hashes)?)
+            }
+            "local" => Box::new(LocalSource::new(trace_dir()?)?),
+            "mock" => Box::new(MockRpcSource::new(
+                trace_dir()?,
+                &self.mock_rpc_addr,
+                self.batches,
+                self.history_hashes,
+            )?),
+            s => bail!("unknown trace source {s}, expected kroma, local or mock"),
+        })
+    }
+}
@@ -0,0 +1,179 @@
+use anyhow::Result;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+use zkevm::circuit::SUB_CIRCUIT_NAMES;
+use zkevm::io::write_file_atomically;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchResult {
+    pub index: i64,
+    pub first_block: Option<u64>,
+    pub last_block: Option<u64>,
+    pub blocks: usize,
+    pub txs: usize,
+    pub gas: u64,
+    /// rows per sub circuit, see `SUB_CIRCUIT_NAMES`. Empty if the witness failed.
+    pub rows: Vec<usize>,
+    pub passed: bool,
+    pub error: Option<String>,
+}
+
+impl BatchResult {
+    pub fn max_rows(&self) -> usize {
+        self.rows.iter().copied().max().unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ProgressFile {
+    key: String,
+    results: Vec<BatchResult>,
+}
+
+/// Results of the batches run so far, saved after every batch so that a run can be resumed.
+/// The results are only resumed by a run with the same `key`, e.g. of the same circuits.
+pub struct Progress {
+    path: Option<PathBuf>,
+    key: String,
+    results: BTreeMap<i64, BatchResult>,
+}
+
+impl Progress {
+    /// load the progress file, or start over if there is no file or it was saved with
+    /// another key.
+    pub fn load(path: Option<&str>, key: String) -> Result<Self> {
+        let path = path.map(PathBuf::from);
+        let results = match &path {
+            Some(path) if path.exists() => {
+                match serde_json::from_slice::<ProgressFile>(&fs::read(path)?) {
+                    Ok(file) if file.key == key => {
+                        log::info!(
+                            "mock-testnet: resume with {} batches run in {:?}",
+                            file.results.len(),
+                            path
+                        );
+                        file.results.into_iter().map(|r| (r.index, r)).collect()
+                    }
+                    Ok(file) => {
+                        log::warn!(
+                            "mock-testnet: start over, progress {:?} is of {}, not {}",
+                            path,
+                            file.key,
+                            key
+                        );
+                        BTreeMap::new()
+                    }
+                    Err(e) => {
+                        log::warn!("mock-testnet: start over, invalid progress {path:?}: {e}");
+                        BTreeMap::new()
+                    }
+                }
+            }
+            _ => BTreeMap::new(),
+        };
+        Ok(Self { path, key, results })
+    }
+
+    /// whether the batch passed already. Failed batches are run again.
+    pub fn is_done(&self, index: i64) -> bool {
+        self.results.get(&index).map_or(false, |r| r.passed)
+    }
+
+    pub fn record(&mut self, result: BatchResult) {
+        self.results.insert(result.index, result);
+        if let Err(e) = self.save() {
+            log::error!("mock-testnet: failed to save progress: {e}");
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(path) = &self.path {
+            let file = ProgressFile {
+                key: self.key.clone(),
+                results: self.results.values().cloned().collect(),
+            };
+            write_file_atomically(path, &serde_json::to_vec_pretty(&file)?)?;
+        }
+        Ok(())
+    }
+
+    /// return a table of the results per batch, followed by rows and gas statistics.
+    pub fn summary(&self) -> String {
+        let mut out = String::new();
+        writeln!(
+            out,
+            "{:>8} {:>21} {:>6} {:>6} {:>12} {:>10} {:>8} {:<8} {}",
+            "batch", "blocks", "count", "txs", "gas", "rows", "gas/row", "result", "bottleneck"
+        )
+        .unwrap();
+        for r in self.results.values() {
+            let range = match (r.first_block, r.last_block) {
+                (Some(first), Some(last)) => format!("{first}-{last}"),
+                _ => "-".to_string(),
+            };
+            let max_rows = r.max_rows();
+            let bottleneck = r
+                .rows
+                .iter()
+                .position(|rows| *rows == max_rows && max_rows > 0)
+                .and_then(|idx| SUB_CIRCUIT_NAMES.get(idx))
+                .unwrap_or(&"-");
+            writeln!(
+                out,
+                "{:>8} {:>21} {:>6} {:>6} {:>12} {:>10} {:>8.2} {:<8} {}",
+                r.index,
+                range,
+                r.blocks,
+                r.txs,
+                r.gas,
+                max_rows,
+                gas_per_row(r.gas, max_rows),
+                if r.passed { "pass" } else { "FAIL" },
+                bottleneck
+            )
+            .unwrap();
+        }
+
+        let total = self.results.len();
+        let passed = self.results.values().filter(|r| r.passed).count();
+        let with_rows: Vec<_> = self
+            .results
+            .values()
+            .filter(|r| !r.rows.is_empty())
+            .collect();
+        let gas: u64 = with_rows.iter().map(|r| r.gas).sum();
+        let rows: usize = with_rows.iter().map(|r| r.max_rows()).sum();
+        let max_rows = with_rows
+            .iter()
+            .map(|r| r.max_rows())
+            .max()
+            .unwrap_or_default();
+        writeln!(
+            out,
+            "batches: {total}, passed: {passed}, failed: {}",
+            total - passed
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "rows: max {}, avg {:.0}, gas: total {}, avg gas/row {:.2}",
+            max_rows,
+            rows as f64 / with_rows.len().max(1) as f64,
+            gas,
+            gas_per_row(gas, rows)
+        )
+        .unwrap();
+        out
+    }
+}
+
+fn gas_per_row(gas: u64, rows: usize) -> f64 {
+    if rows == 0 {
+        0.0
+    } else {
+        gas as f64 / rows as f64
+    }
+}
@@ -0,0 +1,258 @@
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use bin::local::{block_files, Batches, LocalTraces};
+use ethers_core::types::H256;
+use ethers_providers::{Http, Provider};
+use jsonrpc_core::{IoHandler, Params};
+use jsonrpc_http_server::{Server, ServerBuilder};
+use serde_derive::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use types::eth::{BlockTrace, MAX_HISTORY_HASHES};
+use zkevm::utils::try_get_block_trace_from_file;
+
+/// Kroma node API returning the `BlockTrace` of a block.
+pub const GET_BLOCK_TRACE_METHOD: &str = "kroma_getBlockTraceByNumberOrHash";
+const BLOCK_NUMBER_METHOD: &str = "eth_blockNumber";
+const GET_BLOCK_METHOD: &str = "eth_getBlockByNumber";
+
+/// Where mock_testnet gets the traces of a batch from.
+#[async_trait]
+pub trait TraceSource: Send {
+    /// return the traces of the batch, `None` when there are no more batches.
+    async fn batch(&mut self, index: i64) -> Result<Option<Vec<BlockTrace>>>;
+}
+
+/// Batches as the L2 output oracle submits output roots: batch `i` is the blocks after the
+/// output root `i - 1` up to and including the block of the output root `i`.
+#[derive(Debug, Clone, Copy)]
+pub struct OutputRootBatches {
+    pub starting_block_number: u64,
+    pub submission_interval: u64,
+}
+
+impl OutputRootBatches {
+    /// return the first and last block of the batch.
+    pub fn block_range(&self, index: i64) -> (u64, u64) {
+        let index = index.max(1) as u64;
+        let end = self.starting_block_number + index * self.submission_interval;
+        (end + 1 - self.submission_interval, end)
+    }
+}
+
+/// The fields of `eth_getBlockByNumber` the source needs.
+#[derive(Deserialize)]
+struct BlockHeader {
+    hash: H256,
+}
+
+/// Traces fetched from a Kroma node.
+pub struct KromaRpcSource {
+    provider: Provider<Http>,
+    batches: OutputRootBatches,
+    /// fetch the history hashes of traces coming without them.
+    history_hashes: bool,
+    // hashes of the blocks fetched, shared by the history of consecutive blocks
+    block_hashes: BTreeMap<u64, H256>,
+}
+
+impl KromaRpcSource {
+    pub fn new(url: &str, batches: OutputRootBatches, history_hashes: bool) -> Result<Self> {
+        Ok(Self {
+            provider: Provider::<Http>::try_from(url)?,
+            batches,
+            history_hashes,
+            block_hashes: BTreeMap::new(),
+        })
+    }
+
+    async fn latest_block_number(&self) -> Result<u64> {
+        let number: String = self
+            .provider
+            .request(BLOCK_NUMBER_METHOD, Vec::<()>::new())
+            .await?;
+        Ok(u64::from_str_radix(number.trim_start_matches("0x"), 16)?)
+    }
+
+    async fn block_hash(&mut self, number: u64) -> Result<H256> {
+        if let Some(hash) = self.block_hashes.get(&number) {
+            return Ok(*hash);
+        }
+        let header: Option<BlockHeader> = self
+            .provider
+            .request(GET_BLOCK_METHOD, (format!("{number:#x}"), false))
+            .await?;
+        let hash = header
+            .ok_or_else(|| anyhow!("block {number} not found"))?
+            .hash;
+        self.block_hashes.insert(number, hash);
+        Ok(hash)
+    }
+
+    /// set the hashes of the blocks before the trace, unless the node already did.
+    async fn fill_history_hashes(&mut self, trace: &mut BlockTrace) -> Result<()> {
+        let number = trace
+            .header
+            .number
+            .ok_or_else(|| anyhow!("trace has no block number"))?
+            .as_u64();
+        if let Some(hash) = trace.header.hash {
+            self.block_hashes.insert(number, hash);
+        }
+        if !trace.history_hashes.is_empty() {
+            return Ok(());
+        }
+
+        let first = number.saturating_sub(MAX_HISTORY_HASHES as u64);
+        let mut history_hashes = Vec::with_capacity((number - first) as usize);
+        for n in first..number {
+            history_hashes.push(self.block_hash(n).await?);
+        }
+        trace.history_hashes = history_hashes;
+        // later blocks of the batch do not look further back
+        self.block_hashes = self.block_hashes.split_off(&first);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TraceSource for KromaRpcSource {
+    async fn batch(&mut self, index: i64) -> Result<Option<Vec<BlockTrace>>> {
+        let (start, end) = self.batches.block_range(index);
+        // the output root of the batch is not there yet
+        if end > self.latest_block_number().await? {
+            return Ok(None);
+        }
+
+        let mut traces = vec![];
+        for number in start..=end {
+            log::info!("mock-testnet: requesting trace of block {number}");
+            let mut trace: BlockTrace = self
+                .provider
+                .request(GET_BLOCK_TRACE_METHOD, [format!("{number:#x}")])
+                .await?;
+            if self.history_hashes {
+                self.fill_history_hashes(&mut trace).await?;
+            }
+            traces.push(trace);
+        }
+        Ok(Some(traces))
+    }
+}
+
+/// Traces read from a local dir or archive, see `LocalTraces`.
+pub struct LocalSource {
+    batches: Batches,
+    // the batch read ahead while looking for a missing one
+    pending: Option<(i64, Result<Vec<BlockTrace>>)>,
+}
+
+impl LocalSource {
+    pub fn new(path: &str) -> Result<Self> {
+        Ok(Self {
+            batches: LocalTraces::open(path)?.into_batches()?,
+            pending: None,
+        })
+    }
+}
+
+#[async_trait]
+impl TraceSource for LocalSource {
+    async fn batch(&mut self, index: i64) -> Result<Option<Vec<BlockTrace>>> {
+        // batches come in order, so skip the ones before
+        while let Some((i, traces)) = self.pending.take().or_else(|| self.batches.next()) {
+            if i == index {
+                return traces.map(Some);
+            }
+            if i > index {
+                self.pending = Some((i, traces));
+                bail!("batch {index} is missing, next one is {i}");
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// A JSON-RPC server mocking the Kroma node API with the trace files of a dir, named
+/// `<block number>.json`. Traces are fetched from it like from a node, so that the RPC path
+/// can be exercised without a node. Only the blocks of the dir have a hash.
+pub struct MockRpcSource {
+    inner: KromaRpcSource,
+    server: Option<Server>,
+}
+
+impl MockRpcSource {
+    pub fn new(
+        dir: &str,
+        addr: &str,
+        batches: OutputRootBatches,
+        history_hashes: bool,
+    ) -> Result<Self> {
+        let files: Arc<BTreeMap<u64, PathBuf>> = Arc::new(block_files(dir)?);
+        let latest = *files
+            .keys()
+            .last()
+            .ok_or_else(|| anyhow!("no trace in {dir}"))?;
+
+        let mut io = IoHandler::new();
+        io.add_sync_method(BLOCK_NUMBER_METHOD, move |_| {
+            Ok(format!("{latest:#x}").into())
+        });
+        let block_files = files.clone();
+        io.add_sync_method(GET_BLOCK_METHOD, move |params: Params| {
+            let (number, _): (String, bool) = params.parse()?;
+            let header = u64::from_str_radix(number.trim_start_matches("0x"), 16)
+                .ok()
+                .and_then(|n| block_files.get(&n))
+                .map(|path| load_trace(path).map(|trace| trace.header))
+                .transpose()?;
+            Ok(header.map_or(
+                serde_json::Value::Null,
+                |header| serde_json::json!({ "number": header.number, "hash": header.hash }),
+            ))
+        });
+        io.add_sync_method(GET_BLOCK_TRACE_METHOD, move |params: Params| {
+            let (number,): (String,) = params.parse()?;
+            let path = u64::from_str_radix(number.trim_start_matches("0x"), 16)
+                .ok()
+                .and_then(|n| files.get(&n))
+                .ok_or_else(|| jsonrpc_core::Error::invalid_params("unknown block"))?;
+            let trace = load_trace(path)?;
+            serde_json::to_value(trace).map_err(|_| jsonrpc_core::Error::internal_error())
+        });
+
+        let server = ServerBuilder::new(io)
+            .event_loop_executor(tokio::runtime::Handle::current())
+            .start_http(&addr.parse()?)?;
+        log::info!("mock-testnet: mock rpc listening on {}", server.address());
+        let url = format!("http://{}", server.address());
+        Ok(Self {
+            inner: KromaRpcSource::new(&url, batches, history_hashes)?,
+            server: Some(server),
+        })
+    }
+}
+
+// a broken trace file fails the request only.
+fn load_trace(path: &Path) -> jsonrpc_core::Result<BlockTrace> {
+    try_get_block_trace_from_file(path).map_err(|e| {
+        log::error!("mock-testnet: failed to serve {path:?}: {e:?}");
+        jsonrpc_core::Error::internal_error()
+    })
+}
+
+#[async_trait]
+impl TraceSource for MockRpcSource {
+    async fn batch(&mut self, index: i64) -> Result<Option<Vec<BlockTrace>>> {
+        self.inner.batch(index).await
+    }
+}
+
+impl Drop for MockRpcSource {
+    fn drop(&mut self) {
+        if let Some(server) = self.server.take() {
+            server.close();
+        }
+    }
+}
@@ -3,16 +3,17 @@ use rand::SeedableRng;
 use rand_xorshift::XorShiftRng;
 use std::collections::HashMap;
 use std::fs;
-use std::fs::File;
-use std::io::Write;
 use std::path::PathBuf;
+use types::compact;
 use utils::check_chain_id;
 use utils::Measurer;
 use zkevm::{
-    circuit::{EvmCircuit, StateCircuit, AGG_DEGREE, DEGREE, MAX_TXS},
-    io::write_file,
+    checkpoint::CheckpointStore,
+    circuit::{AGG_DEGREE, CIRCUIT_REGISTRY, DEGREE, MAX_TXS, WITNESS_CACHE},
+    io::{write_file, write_file_atomically},
     prover::Prover,
     utils::{get_block_trace_from_file, load_kzg_params, load_or_create_seed},
+    verifier::Verifier,
 };
 
 #[derive(Parser, Debug)]
@@ -35,19 +36,36 @@ struct Args {
     /// Boolean means if output state proof.
     #[clap(long = "state")]
     state_proof: Option<bool>,
+    /// Generates the proof of the circuit and outputs it as `<circuit>.proof`, one of the
+    /// circuit registry, e.g. super or zktrie. Can be given several times.
+    #[clap(long = "circuit")]
+    circuits: Vec<String>,
     /// Option means if generates agg proof.
     /// Boolean means if output agg proof.
     #[clap(long = "agg")]
     agg_proof: Option<bool>,
+    /// Keep stage checkpoints of agg proofs in the dir, and resume from them.
+    #[clap(long = "checkpoint")]
+    checkpoint_dir: Option<String>,
 }
 
 fn main() {
     dotenv::dotenv().ok();
     env_logger::init();
+    // the capacity check and the proof of a batch build the same witness blocks
+    WITNESS_CACHE.opt_in(2);
 
     let chain_id = check_chain_id();
     log::info!("chain_id: {chain_id}");
     let args = Args::parse();
+    for name in &args.circuits {
+        if CIRCUIT_REGISTRY.get(name).is_none() {
+            panic!(
+                "unknown circuit {name}, expected one of {:?}",
+                CIRCUIT_REGISTRY.names()
+            );
+        }
+    }
 
     // Prepare KZG params and rng for prover
     let mut timer = Measurer::new();
@@ -62,13 +80,24 @@ fn main() {
     let mut prover = Prover::from_params_and_rng(params, agg_params, rng);
     timer.end("finish loading params");
 
+    // checkpointed proofs are verified before they are reused
+    let mut checkpoints = args.checkpoint_dir.as_ref().map(|dir| {
+        let store = CheckpointStore::new(dir).expect("cannot create checkpoint dir");
+        let verifier =
+            Verifier::from_params(prover.params.clone(), prover.agg_params.clone(), None);
+        (store, verifier)
+    });
+
     // Getting traces from specific directory
     let mut traces = HashMap::new();
     let trace_path = PathBuf::from(&args.trace_path.unwrap());
     if trace_path.is_dir() {
         for entry in fs::read_dir(trace_path).unwrap() {
             let path = entry.unwrap().path();
-            if path.is_file() && path.to_str().unwrap().ends_with(".json") {
+            let is_trace = path
+                .extension()
+                .map_or(false, |ext| ext == "json" || ext == compact::EXTENSION);
+            if path.is_file() && is_trace {
                 let block_trace = get_block_trace_from_file(path.to_str().unwrap());
                 traces.insert(path.file_stem().unwrap().to_os_string(), block_trace);
             }
@@ -96,38 +125,37 @@ fn main() {
 
         timer.start();
         prover.debug_dir = String::from(out_dir.to_str().unwrap());
-        if args.evm_proof.is_some() {
-            let proof_path = PathBuf::from(&trace_name).join("evm.proof");
-
-            let evm_proof = prover
-                .create_target_circuit_proof::<EvmCircuit>(&trace)
-                .expect("cannot generate evm_proof");
-
-            if args.evm_proof.unwrap() {
-                let mut f = File::create(&proof_path).unwrap();
-                f.write_all(evm_proof.proof.as_slice()).unwrap();
-            }
-        }
-
-        if args.state_proof.is_some() {
-            let proof_path = PathBuf::from(&trace_name).join("state.proof");
-
-            let state_proof = prover
-                .create_target_circuit_proof::<StateCircuit>(&trace)
-                .expect("cannot generate state_proof");
-
-            if args.state_proof.unwrap() {
-                let mut f = File::create(&proof_path).unwrap();
-                f.write_all(state_proof.proof.as_slice()).unwrap();
+        // the legacy flags, which may only generate the proof without writing it
+        let target_proofs = args
+            .evm_proof
+            .map(|output| ("evm", output))
+            .into_iter()
+            .chain(args.state_proof.map(|output| ("state", output)))
+            .chain(args.circuits.iter().map(|name| (name.as_str(), true)));
+        for (name, output) in target_proofs {
+            let proof_path = PathBuf::from(&trace_name).join(format!("{name}.proof"));
+
+            let proof = CIRCUIT_REGISTRY
+                .get(name)
+                .unwrap()
+                .prove(&mut prover, std::slice::from_ref(&trace))
+                .unwrap_or_else(|e| panic!("cannot generate {name}_proof: {e:?}"));
+
+            if output {
+                write_file_atomically(&proof_path, &proof.proof).unwrap();
             }
         }
 
         if args.agg_proof.is_some() {
             let mut proof_path = PathBuf::from(&trace_name).join("agg.proof");
 
-            let agg_proof = prover
-                .create_agg_circuit_proof(&trace)
-                .expect("cannot generate agg_proof");
+            let agg_proof = match &mut checkpoints {
+                Some((store, verifier)) => {
+                    prover.resume(std::slice::from_ref(&trace), store, verifier)
+                }
+                None => prover.create_agg_circuit_proof(&trace),
+            }
+            .expect("cannot generate agg_proof");
 
             if args.agg_proof.unwrap() {
                 fs::create_dir_all(&proof_path).unwrap();
@@ -142,6 +170,12 @@ fn main() {
             );
             log::info!("output files to {}", out_dir.to_str().unwrap());
         }
+        let profile = prover.take_profile();
+        write_file(
+            &mut out_dir,
+            "profile.json",
+            &serde_json::to_vec_pretty(&profile).unwrap(),
+        );
         timer.end("finish generating a proof");
     }
     outer_timer.end("finish generating all");
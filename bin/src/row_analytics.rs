@@ -0,0 +1,281 @@
+use anyhow::Result;
+use bin::local::LocalTraces;
+use clap::Parser;
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+use types::eth::BlockTrace;
+use zkevm::circuit::{usable_rows, SUB_CIRCUIT_NAMES, WITNESS_CACHE};
+
+/// Row usage of every sub circuit over a corpus of traces, per block and per batch, written as
+/// `blocks.csv`, `batches.csv` and `row_usage.json` with a histogram of the bottleneck circuits.
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// Dir or archive of traces, in any layout of the local traces of mock-testnet: recorded
+    /// fixtures, batch dirs, block files grouped by `batches.json`, or a batch per line.
+    #[clap(short, long = "trace")]
+    trace_dir: String,
+    /// Dir to write the reports into.
+    #[clap(short, long = "out", default_value = "row_analytics")]
+    out_dir: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct BlockUsage {
+    batch: i64,
+    block_number: u64,
+    txs: usize,
+    gas: u64,
+    calldata: usize,
+    /// rows per sub circuit, see `SUB_CIRCUIT_NAMES`. Empty if the witness failed.
+    rows: Vec<usize>,
+    error: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct BatchUsage {
+    batch: i64,
+    blocks: usize,
+    txs: usize,
+    gas: u64,
+    calldata: usize,
+    /// rows of the whole batch per sub circuit. Empty if the witness failed.
+    rows: Vec<usize>,
+    bottleneck: Option<String>,
+    /// max rows over the usable rows of the circuit.
+    utilization: f64,
+    failed_blocks: usize,
+    error: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct Report {
+    sub_circuits: Vec<String>,
+    usable_rows: usize,
+    blocks: Vec<BlockUsage>,
+    batches: Vec<BatchUsage>,
+    /// number of batches and of blocks each sub circuit is the bottleneck of.
+    batch_bottlenecks: BTreeMap<String, usize>,
+    block_bottlenecks: BTreeMap<String, usize>,
+}
+
+fn bottleneck(rows: &[usize]) -> Option<String> {
+    let max_rows = rows.iter().copied().max().filter(|r| *r > 0)?;
+    let idx = rows.iter().position(|r| *r == max_rows)?;
+    Some(SUB_CIRCUIT_NAMES[idx].to_string())
+}
+
+fn histogram<'a>(bottlenecks: impl Iterator<Item = &'a Option<String>>) -> BTreeMap<String, usize> {
+    let mut histogram: BTreeMap<String, usize> = SUB_CIRCUIT_NAMES
+        .iter()
+        .map(|name| (name.to_string(), 0))
+        .collect();
+    for name in bottlenecks.flatten() {
+        *histogram.get_mut(name).unwrap() += 1;
+    }
+    histogram
+}
+
+fn block_usage(batch: i64, block_trace: &BlockTrace) -> BlockUsage {
+    let (rows, error) = match WITNESS_CACHE.row_usage(std::slice::from_ref(block_trace)) {
+        Ok(rows) => (rows, None),
+        Err(e) => {
+            log::error!(
+                "failed to count rows of block {:?}: {e:?}",
+                block_trace.header.number
+            );
+            (vec![], Some(e.to_string()))
+        }
+    };
+    BlockUsage {
+        batch,
+        block_number: block_trace.header.number.unwrap_or_default().as_u64(),
+        txs: block_trace.transactions.len(),
+        gas: block_trace.header.gas_used.as_u64(),
+        calldata: block_trace.transactions.iter().map(|t| t.data.len()).sum(),
+        rows,
+        error,
+    }
+}
+
+fn batch_usage(
+    batch: i64,
+    block_traces: &[BlockTrace],
+    blocks: &[BlockUsage],
+    usable_rows: usize,
+) -> BatchUsage {
+    let (rows, error) = match WITNESS_CACHE.row_usage(block_traces) {
+        Ok(rows) => (rows, None),
+        Err(e) => {
+            log::error!("failed to count rows of batch {batch}: {e:?}");
+            (vec![], Some(e.to_string()))
+        }
+    };
+    let max_rows = rows.iter().copied().max().unwrap_or_default();
+    BatchUsage {
+        batch,
+        blocks: blocks.len(),
+        txs: blocks.iter().map(|b| b.txs).sum(),
+        gas: blocks.iter().map(|b| b.gas).sum(),
+        calldata: blocks.iter().map(|b| b.calldata).sum(),
+        bottleneck: bottleneck(&rows),
+        rows,
+        utilization: max_rows as f64 / usable_rows as f64,
+        failed_blocks: blocks.iter().filter(|b| b.error.is_some()).count(),
+        error,
+    }
+}
+
+fn blocks_csv(blocks: &[BlockUsage]) -> String {
+    let mut out = format!(
+        "batch,block_number,txs,gas,calldata,{},error\n",
+        SUB_CIRCUIT_NAMES.join(",")
+    );
+    for b in blocks {
+        let (rows, error) = csv_rows_and_error(&b.rows, &b.error);
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{}",
+            b.batch, b.block_number, b.txs, b.gas, b.calldata, rows, error
+        )
+        .unwrap();
+    }
+    out
+}
+
+fn csv_rows_and_error(rows: &[usize], error: &Option<String>) -> (String, String) {
+    let rows = if rows.is_empty() {
+        vec![String::new(); SUB_CIRCUIT_NAMES.len()]
+    } else {
+        rows.iter().map(|r| r.to_string()).collect()
+    };
+    // errors may contain anything, keep them in one quoted field
+    let error = error
+        .as_ref()
+        .map(|e| format!("\"{}\"", e.replace('"', "\"\"")))
+        .unwrap_or_default();
+    (rows.join(","), error)
+}
+
+fn batches_csv(batches: &[BatchUsage]) -> String {
+    let mut out = format!(
+        "batch,blocks,txs,gas,calldata,{},bottleneck,utilization,failed_blocks,error\n",
+        SUB_CIRCUIT_NAMES.join(",")
+    );
+    for b in batches {
+        let (rows, error) = csv_rows_and_error(&b.rows, &b.error);
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{},{:.4},{},{}",
+            b.batch,
+            b.blocks,
+            b.txs,
+            b.gas,
+            b.calldata,
+            rows,
+            b.bottleneck.as_deref().unwrap_or_default(),
+            b.utilization,
+            b.failed_blocks,
+            error
+        )
+        .unwrap();
+    }
+    out
+}
+
+/// return the histogram as a text chart.
+fn histogram_chart(histogram: &BTreeMap<String, usize>) -> String {
+    const WIDTH: usize = 40;
+    let total = histogram.values().sum::<usize>().max(1);
+    let mut out = String::new();
+    for (name, count) in histogram {
+        writeln!(
+            out,
+            "{:>10} {:>6} {}",
+            name,
+            count,
+            "#".repeat(count * WIDTH / total)
+        )
+        .unwrap();
+    }
+    out
+}
+
+fn main() -> Result<()> {
+    dotenv::dotenv().ok();
+    env_logger::init();
+
+    let args = Args::parse();
+    let usable_rows = usable_rows();
+
+    let mut blocks = vec![];
+    let mut batches = vec![];
+    for (index, block_traces) in LocalTraces::open(&args.trace_dir)?.into_batches()? {
+        let block_traces = match block_traces {
+            Ok(block_traces) => block_traces,
+            Err(e) => {
+                log::error!("failed to load batch {index}: {e:?}");
+                continue;
+            }
+        };
+        log::info!(
+            "counting rows of batch {index}, {} blocks",
+            block_traces.len()
+        );
+
+        let batch_blocks: Vec<_> = block_traces
+            .iter()
+            .map(|block_trace| block_usage(index, block_trace))
+            .collect();
+        batches.push(batch_usage(
+            index,
+            &block_traces,
+            &batch_blocks,
+            usable_rows,
+        ));
+        blocks.extend(batch_blocks);
+    }
+
+    let report = Report {
+        sub_circuits: SUB_CIRCUIT_NAMES.iter().map(|n| n.to_string()).collect(),
+        usable_rows,
+        batch_bottlenecks: histogram(batches.iter().map(|b| &b.bottleneck)),
+        block_bottlenecks: histogram(
+            blocks
+                .iter()
+                .map(|b| bottleneck(&b.rows))
+                .collect::<Vec<_>>()
+                .iter(),
+        ),
+        blocks,
+        batches,
+    };
+
+    let out_dir = PathBuf::from(&args.out_dir);
+    fs::create_dir_all(&out_dir)?;
+    fs::write(out_dir.join("blocks.csv"), blocks_csv(&report.blocks))?;
+    fs::write(out_dir.join("batches.csv"), batches_csv(&report.batches))?;
+    fs::write(
+        out_dir.join("row_usage.json"),
+        serde_json::to_vec_pretty(&report)?,
+    )?;
+
+    log::info!(
+        "{} blocks in {} batches, written to {:?}",
+        report.blocks.len(),
+        report.batches.len(),
+        out_dir
+    );
+    log::info!(
+        "bottleneck of batches:\n{}",
+        histogram_chart(&report.batch_bottlenecks)
+    );
+    log::info!(
+        "bottleneck of blocks:\n{}",
+        histogram_chart(&report.block_bottlenecks)
+    );
+    Ok(())
+}
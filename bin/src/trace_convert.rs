@@ -0,0 +1,88 @@
+use clap::Parser;
+use std::fs;
+use std::path::{Path, PathBuf};
+use types::compact;
+use zkevm::utils::get_block_trace_from_file;
+
+/// Convert BlockTraces between JSON and the compact binary format.
+/// The conversion is lossless for every field BlockTrace models, and each output is checked
+/// by reading it and the input again.
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// Trace file or dir of trace files.
+    #[clap(short, long = "trace")]
+    trace_path: String,
+    /// Output dir, defaults to the dir of each input.
+    #[clap(short, long = "out")]
+    out_dir: Option<String>,
+    /// Convert compact traces back to JSON.
+    #[clap(long = "to-json")]
+    to_json: bool,
+}
+
+fn convert(path: &Path, out_dir: Option<&Path>, to_json: bool) -> anyhow::Result<()> {
+    let trace = get_block_trace_from_file(path);
+    let (ext, buf) = if to_json {
+        ("json", serde_json::to_vec(&trace)?)
+    } else {
+        (compact::EXTENSION, compact::encode_block_trace(&trace)?)
+    };
+
+    let out_path = match out_dir {
+        Some(dir) => dir.join(path.file_name().unwrap()),
+        None => path.to_path_buf(),
+    }
+    .with_extension(ext);
+    fs::write(&out_path, &buf)?;
+
+    // read both files again, so that the check does not depend on the trace converted
+    let source = get_block_trace_from_file(path);
+    let written = get_block_trace_from_file(&out_path);
+    if serde_json::to_value(&written)? != serde_json::to_value(&source)? {
+        fs::remove_file(&out_path)?;
+        anyhow::bail!("{:?} does not survive the conversion", path);
+    }
+    log::info!(
+        "{:?} ({} bytes) -> {:?} ({} bytes)",
+        path,
+        fs::metadata(path)?.len(),
+        out_path,
+        buf.len()
+    );
+    Ok(())
+}
+
+fn main() {
+    dotenv::dotenv().ok();
+    env_logger::init();
+
+    let args = Args::parse();
+    let out_dir = args.out_dir.map(PathBuf::from);
+    if let Some(dir) = &out_dir {
+        fs::create_dir_all(dir).expect("cannot create output dir");
+    }
+
+    let input_ext = if args.to_json {
+        compact::EXTENSION
+    } else {
+        "json"
+    };
+    let trace_path = PathBuf::from(&args.trace_path);
+    let paths = if trace_path.is_dir() {
+        let mut paths: Vec<_> = fs::read_dir(&trace_path)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.is_file() && path.extension().map_or(false, |e| e == input_ext))
+            .collect();
+        paths.sort();
+        paths
+    } else {
+        vec![trace_path]
+    };
+
+    for path in paths {
+        convert(&path, out_dir.as_deref(), args.to_json)
+            .unwrap_or_else(|e| panic!("failed to convert {path:?}: {e}"));
+    }
+}
@@ -0,0 +1,117 @@
+use clap::Parser;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+use types::compact;
+use types::eth::BlockTrace;
+use zkevm::circuit::{CircuitEntry, CIRCUIT_REGISTRY};
+use zkevm::minimizer::{failure_signature, minimize};
+use zkevm::utils::get_block_trace_from_file;
+
+/// Shrink a trace, or a batch of traces, which fails mock proving to the smallest batch which
+/// still fails the same constraints.
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// Failing BlockTrace file, or dir of the traces of a failing batch.
+    #[clap(short, long = "trace")]
+    trace_path: String,
+    /// Circuit to mock prove, one of the circuit registry, e.g. super or evm.
+    #[clap(long = "circuit", default_value = "super")]
+    circuit: String,
+    /// Only keep the failures whose description contains the text.
+    #[clap(long = "match")]
+    failure_match: Option<String>,
+    /// Dir to write the minimized traces into.
+    #[clap(short, long = "out", default_value = "minimized")]
+    out_dir: String,
+}
+
+fn signature(circuit: &CircuitEntry, block_traces: &[BlockTrace]) -> Option<BTreeSet<String>> {
+    match circuit.mock_prove_failures(block_traces) {
+        Ok(failures) => Some(failure_signature(&circuit.name, &failures)),
+        Err(e) => {
+            log::info!("not reproduced, batch can not be mock proved: {}", e);
+            None
+        }
+    }
+}
+
+fn run(
+    circuit: &CircuitEntry,
+    block_traces: Vec<BlockTrace>,
+    failure_match: Option<&str>,
+) -> Vec<BlockTrace> {
+    // the failures a candidate has to fail with, the same and no others
+    let matching = |mut signature: BTreeSet<String>| {
+        if let Some(text) = failure_match {
+            signature.retain(|failure| failure.contains(text));
+        }
+        signature
+    };
+    let target =
+        matching(signature(circuit, &block_traces).expect("the traces can not be mock proved"));
+    if target.is_empty() {
+        panic!("the traces do not fail mock proving with the given failure");
+    }
+    log::info!("minimizing for failures {:#?}", target);
+
+    let mut attempts = 0;
+    let minimized = minimize(block_traces, |candidate| {
+        attempts += 1;
+        let reproduced = signature(circuit, candidate).map_or(false, |s| matching(s) == target);
+        log::info!("attempt {}: reproduced {}", attempts, reproduced);
+        reproduced
+    });
+    log::info!("minimized in {} attempts", attempts);
+    minimized
+}
+
+fn main() {
+    dotenv::dotenv().ok();
+    env_logger::init();
+
+    let args = Args::parse();
+    let trace_path = PathBuf::from(&args.trace_path);
+    let mut block_traces: Vec<BlockTrace> = if trace_path.is_dir() {
+        fs::read_dir(&trace_path)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| {
+                path.is_file()
+                    && path
+                        .extension()
+                        .map_or(false, |ext| ext == "json" || ext == compact::EXTENSION)
+            })
+            .map(get_block_trace_from_file)
+            .collect()
+    } else {
+        vec![get_block_trace_from_file(&trace_path)]
+    };
+    block_traces.sort_by_key(|t| t.header.number);
+    let original_tx_count: usize = block_traces.iter().map(|t| t.transactions.len()).sum();
+
+    let failure_match = args.failure_match.as_deref();
+    let circuit = CIRCUIT_REGISTRY
+        .get(&args.circuit)
+        .unwrap_or_else(|| panic!("invalid circuit: {}", args.circuit));
+    let minimized = run(circuit, block_traces.clone(), failure_match);
+
+    fs::create_dir_all(&args.out_dir).expect("cannot create output dir");
+    for block_trace in &minimized {
+        let number = block_trace.header.number.unwrap_or_default();
+        let path = PathBuf::from(&args.out_dir).join(format!("{number}.json"));
+        fs::write(&path, serde_json::to_vec_pretty(block_trace).unwrap()).unwrap();
+        log::info!("written {:?}", path);
+    }
+    log::info!(
+        "blocks {} -> {}, txs {} -> {}",
+        block_traces.len(),
+        minimized.len(),
+        original_tx_count,
+        minimized
+            .iter()
+            .map(|t| t.transactions.len())
+            .sum::<usize>()
+    );
+}
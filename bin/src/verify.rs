@@ -5,7 +5,7 @@ use std::io::Read;
 use zkevm::prover::{AggCircuitProof, TargetCircuitProof};
 use zkevm::verifier::Verifier;
 use zkevm::{
-    circuit::{EvmCircuit, StateCircuit, AGG_DEGREE, DEGREE},
+    circuit::{AGG_DEGREE, CIRCUIT_REGISTRY, DEGREE},
     utils::load_kzg_params,
 };
 
@@ -24,6 +24,10 @@ struct Args {
     /// the path of state proof to verify.
     #[clap(long = "state")]
     state_proof: Option<String>,
+    /// the circuit and the path of its proof to verify as `<circuit>:<path>`, the circuit one of
+    /// the circuit registry, e.g. super or zktrie. Can be given several times.
+    #[clap(long = "circuit")]
+    circuit_proofs: Vec<String>,
     /// the path of agg proof to verify.
     #[clap(long = "agg")]
     agg_proof: Option<String>,
@@ -41,19 +45,28 @@ fn main() {
     let agg_vk = read_from_file(&args.vk_path.unwrap());
 
     let mut v = Verifier::from_params(params, agg_params, Some(agg_vk));
-    if let Some(path) = args.evm_proof {
+    let circuit_proofs = args
+        .evm_proof
+        .map(|path| ("evm".to_string(), path))
+        .into_iter()
+        .chain(args.state_proof.map(|path| ("state".to_string(), path)))
+        .chain(args.circuit_proofs.iter().map(|arg| {
+            let (name, path) = arg
+                .split_once(':')
+                .unwrap_or_else(|| panic!("expected <circuit>:<path>, but {arg}"));
+            (name.to_string(), path.to_string())
+        }));
+    for (name, path) in circuit_proofs {
         let proof_vec = read_from_file(&path);
         let proof = serde_json::from_slice::<TargetCircuitProof>(proof_vec.as_slice()).unwrap();
-        let verified = v.verify_target_circuit_proof::<EvmCircuit>(&proof).is_ok();
-        info!("verify evm proof: {}", verified)
-    }
-    if let Some(path) = args.state_proof {
-        let proof_vec = read_from_file(&path);
-        let proof = serde_json::from_slice::<TargetCircuitProof>(proof_vec.as_slice()).unwrap();
-        let verified = v
-            .verify_target_circuit_proof::<StateCircuit>(&proof)
-            .is_ok();
-        info!("verify state proof: {}", verified)
+        let circuit = CIRCUIT_REGISTRY.get(&name).unwrap_or_else(|| {
+            panic!(
+                "unknown circuit {name}, expected one of {:?}",
+                CIRCUIT_REGISTRY.names()
+            )
+        });
+        let verified = circuit.verify(&mut v, &proof).is_ok();
+        info!("verify {} proof: {}", name, verified)
     }
     if let Some(path) = args.agg_proof {
         let proof_vec = read_from_file(&path);
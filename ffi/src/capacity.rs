@@ -0,0 +1,52 @@
+use crate::utils::{c_char_to_vec, vec_to_c_char};
+use libc::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use types::eth::{validate, BlockTrace};
+use zkevm::circuit::{check_capacity as check_trace_capacity, usable_rows, CapacityCheck};
+
+/// Return the `CapacityCheck` of the trace as JSON, or `{"error": <message>}` if the trace or the
+/// usage is invalid. `usage` is a JSON array of the rows used so far per sub circuit, or null.
+/// `max_rows` is the max rows of the chain's capacity profile, `usable_rows()` if 0.
+///
+/// # Safety
+#[no_mangle]
+pub unsafe extern "C" fn check_capacity(
+    trace_char: *const c_char,
+    usage_char: *const c_char,
+    max_rows: usize,
+) -> *const c_char {
+    let trace_vec = c_char_to_vec(trace_char);
+    let usage_vec = (!usage_char.is_null()).then(|| c_char_to_vec(usage_char));
+    // a panic must not unwind into the host
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        check(&trace_vec, usage_vec.as_deref(), max_rows)
+    }))
+    .unwrap_or_else(|_| Err("capacity check panicked".to_string()));
+    let check_bytes = match result {
+        Ok(check) => serde_json::to_vec(&check),
+        Err(error) => serde_json::to_vec(&serde_json::json!({ "error": error })),
+    }
+    .unwrap();
+    vec_to_c_char(check_bytes)
+}
+
+fn check(trace: &[u8], usage: Option<&[u8]>, max_rows: usize) -> Result<CapacityCheck, String> {
+    let trace = serde_json::from_slice::<BlockTrace>(trace)
+        .map_err(|e| format!("invalid format trace: {e}"))?;
+    let issues = validate(&trace);
+    if !issues.is_empty() {
+        let issues: Vec<_> = issues.iter().map(ToString::to_string).collect();
+        return Err(format!("invalid block trace: {}", issues.join(", ")));
+    }
+    let usage: Vec<usize> = usage
+        .map(serde_json::from_slice)
+        .transpose()
+        .map_err(|e| format!("invalid usage: {e}"))?
+        .unwrap_or_default();
+    let max_rows = if max_rows == 0 {
+        usable_rows()
+    } else {
+        max_rows
+    };
+    check_trace_capacity(&trace, &usage, max_rows).map_err(|e| format!("{e:?}"))
+}
@@ -1,5 +1,6 @@
 #![feature(once_cell)]
 
+pub mod capacity;
 pub mod prove;
 pub mod verify;
 
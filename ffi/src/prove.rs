@@ -1,7 +1,9 @@
 use crate::utils::{c_char_to_str, c_char_to_vec, vec_to_c_char};
 use libc::c_char;
 use std::cell::OnceCell;
+use types::compact;
 use types::eth::BlockTrace;
+use zkevm::circuit::CIRCUIT_REGISTRY;
 use zkevm::prover::Prover;
 
 static mut PROVER: OnceCell<Prover> = OnceCell::new();
@@ -44,3 +46,37 @@ pub unsafe extern "C" fn create_agg_proof_multi(trace_char: *const c_char) -> *c
     let proof_bytes = serde_json::to_vec(&proof).unwrap();
     vec_to_c_char(proof_bytes)
 }
+
+/// # Safety
+/// `trace` must point to `len` bytes of a compact encoded BlockTrace.
+#[no_mangle]
+pub unsafe extern "C" fn create_agg_proof_compact(trace: *const u8, len: usize) -> *const c_char {
+    let trace_buf = std::slice::from_raw_parts(trace, len);
+    let trace = compact::decode_block_trace(trace_buf).unwrap();
+    let proof = PROVER
+        .get_mut()
+        .unwrap()
+        .create_agg_circuit_proof(&trace)
+        .unwrap();
+    let proof_bytes = serde_json::to_vec(&proof).unwrap();
+    vec_to_c_char(proof_bytes)
+}
+
+/// # Safety
+/// `circuit_char` names a circuit of the circuit registry, e.g. super or zktrie.
+#[no_mangle]
+pub unsafe extern "C" fn create_target_proof(
+    circuit_char: *const c_char,
+    trace_char: *const c_char,
+) -> *const c_char {
+    let circuit = c_char_to_str(circuit_char);
+    let trace_vec = c_char_to_vec(trace_char);
+    let trace = serde_json::from_slice::<BlockTrace>(&trace_vec).unwrap();
+    let proof = CIRCUIT_REGISTRY
+        .get(circuit)
+        .unwrap_or_else(|| panic!("unknown circuit {circuit}"))
+        .prove(PROVER.get_mut().unwrap(), std::slice::from_ref(&trace))
+        .unwrap();
+    let proof_bytes = serde_json::to_vec(&proof).unwrap();
+    vec_to_c_char(proof_bytes)
+}
@@ -2,10 +2,11 @@ use crate::utils::{c_char_to_str, c_char_to_vec};
 use libc::c_char;
 use std::fs::File;
 use std::io::Read;
-use zkevm::prover::AggCircuitProof;
+use zkevm::circuit::CIRCUIT_REGISTRY;
+use zkevm::prover::{AggCircuitProof, TargetCircuitProof};
 use zkevm::verifier::Verifier;
 
-static mut VERIFIER: Option<&Verifier> = None;
+static mut VERIFIER: Option<&mut Verifier> = None;
 
 /// # Safety
 #[no_mangle]
@@ -28,8 +29,27 @@ pub unsafe extern "C" fn verify_agg_proof(proof: *const c_char) -> c_char {
     let proof_vec = c_char_to_vec(proof);
     let agg_proof = serde_json::from_slice::<AggCircuitProof>(proof_vec.as_slice()).unwrap();
     let verified = VERIFIER
+        .as_ref()
         .unwrap()
         .verify_agg_circuit_proof(agg_proof)
         .is_ok();
     verified as c_char
 }
+
+/// # Safety
+/// `circuit_char` names a circuit of the circuit registry, e.g. super or zktrie.
+#[no_mangle]
+pub unsafe extern "C" fn verify_target_proof(
+    circuit_char: *const c_char,
+    proof: *const c_char,
+) -> c_char {
+    let circuit = c_char_to_str(circuit_char);
+    let proof_vec = c_char_to_vec(proof);
+    let target_proof = serde_json::from_slice::<TargetCircuitProof>(proof_vec.as_slice()).unwrap();
+    let verified = CIRCUIT_REGISTRY
+        .get(circuit)
+        .unwrap_or_else(|| panic!("unknown circuit {circuit}"))
+        .verify(VERIFIER.as_mut().unwrap(), &target_proof)
+        .is_ok();
+    verified as c_char
+}
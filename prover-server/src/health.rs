@@ -0,0 +1,100 @@
+//! Liveness, readiness and graceful shutdown of the server.
+
+use crate::utils::kroma_info;
+use once_cell::sync::Lazy;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+
+pub const HEALTH_PATH: &str = "/health";
+pub const READY_PATH: &str = "/ready";
+
+pub static SERVER_STATE: Lazy<ServerState> = Lazy::new(|| ServerState {
+    ready: AtomicBool::new(false),
+    load_error: Mutex::new(None),
+    accepting: Mutex::new(true),
+    in_flight: AtomicUsize::new(0),
+});
+
+#[derive(Debug)]
+pub struct ServerState {
+    // params and keys are loaded
+    ready: AtomicBool,
+    // why the params and keys failed to load, the server is never ready then
+    load_error: Mutex<Option<String>>,
+    // false once shutting down. Jobs are accepted under the lock, so that no job starts after
+    // the shutdown waited for the jobs in flight.
+    accepting: Mutex<bool>,
+    in_flight: AtomicUsize,
+}
+
+impl ServerState {
+    pub fn set_ready(&self) {
+        self.ready.store(true, Ordering::SeqCst);
+    }
+
+    pub fn set_load_failed(&self, error: String) {
+        *self.load_error.lock().unwrap() = Some(error);
+    }
+
+    /// why the params and keys failed to load, reported by `READY_PATH`.
+    pub fn load_error(&self) -> Option<String> {
+        self.load_error.lock().unwrap().clone()
+    }
+
+    /// whether the server takes jobs, reported by `READY_PATH`.
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::SeqCst) && *self.accepting.lock().unwrap()
+    }
+
+    /// start a job, None if the server is shutting down. The job is finished when dropped.
+    pub fn accept_job(&'static self) -> Option<Job> {
+        let accepting = self.accepting.lock().unwrap();
+        if !*accepting {
+            return None;
+        }
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        Some(Job { state: self })
+    }
+
+    /// stop accepting jobs and wait until the jobs in flight are finished.
+    pub fn shutdown(&self) {
+        *self.accepting.lock().unwrap() = false;
+        loop {
+            let in_flight = self.in_flight.load(Ordering::SeqCst);
+            if in_flight == 0 {
+                break;
+            }
+            kroma_info(format!("waiting for {in_flight} jobs to finish"));
+            thread::sleep(Duration::from_secs(5));
+        }
+    }
+}
+
+/// A job accepted by `ServerState::accept_job`.
+pub struct Job {
+    state: &'static ServerState,
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        self.state.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// block until the process gets SIGTERM or SIGINT.
+pub fn wait_for_shutdown_signal() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("failed to build the signal runtime");
+    runtime.block_on(async {
+        let mut terminate = signal(SignalKind::terminate()).expect("failed to listen to SIGTERM");
+        tokio::select! {
+            _ = terminate.recv() => kroma_info("got SIGTERM"),
+            _ = tokio::signal::ctrl_c() => kroma_info("got SIGINT"),
+        }
+    });
+}
@@ -0,0 +1,197 @@
+//! Metrics of the server in the Prometheus text format, served on `/metrics`.
+
+use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use zkevm::circuit::usable_rows;
+use zkevm::metrics::{peak_rss_bytes, PROVER_METRICS, ROW_USAGE_BUCKETS};
+
+pub const METRICS_PATH: &str = "/metrics";
+
+pub static SERVER_METRICS: Lazy<ServerMetrics> = Lazy::new(Default::default);
+
+#[derive(Debug, Clone, Default)]
+struct ProofCounts {
+    started: u64,
+    succeeded: u64,
+    failed: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct ServerMetrics {
+    // by proof type
+    proofs: Mutex<BTreeMap<String, ProofCounts>>,
+    // proofs requested and not finished yet
+    queue_depth: AtomicUsize,
+}
+
+impl ServerMetrics {
+    /// count a proof as started. It counts as failed unless `ProofInFlight::succeed` is called,
+    /// also when proving panics.
+    pub fn start_proof(&'static self, proof_type: String) -> ProofInFlight {
+        self.proofs
+            .lock()
+            .unwrap()
+            .entry(proof_type.clone())
+            .or_default()
+            .started += 1;
+        self.queue_depth.fetch_add(1, Ordering::SeqCst);
+        ProofInFlight {
+            metrics: self,
+            proof_type,
+            succeeded: false,
+        }
+    }
+
+    /// render the metrics of the server and of the `Prover`.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let proofs = self.proofs.lock().unwrap().clone();
+        let proof_counts = [
+            ("started", "Proofs started by proof type."),
+            ("succeeded", "Proofs succeeded by proof type."),
+            ("failed", "Proofs failed by proof type."),
+        ];
+        for (name, help) in proof_counts {
+            let metric = format!("kroma_prover_proofs_{name}_total");
+            header(&mut out, &metric, help, "counter");
+            for (proof_type, counts) in &proofs {
+                let count = match name {
+                    "started" => counts.started,
+                    "succeeded" => counts.succeeded,
+                    _ => counts.failed,
+                };
+                writeln!(out, "{metric}{{proof_type=\"{proof_type}\"}} {count}").unwrap();
+            }
+        }
+
+        header(
+            &mut out,
+            "kroma_prover_queue_depth",
+            "Proofs requested and not finished yet.",
+            "gauge",
+        );
+        writeln!(
+            out,
+            "kroma_prover_queue_depth {}",
+            self.queue_depth.load(Ordering::SeqCst)
+        )
+        .unwrap();
+
+        header(
+            &mut out,
+            "kroma_prover_peak_rss_bytes",
+            "Peak resident set size of the process.",
+            "gauge",
+        );
+        writeln!(out, "kroma_prover_peak_rss_bytes {}", peak_rss_bytes()).unwrap();
+
+        let stages = PROVER_METRICS.stages();
+        header(
+            &mut out,
+            "kroma_prover_stage_duration_seconds",
+            "Durations of the proving stages by circuit.",
+            "summary",
+        );
+        for (stage, circuit, durations) in &stages {
+            let labels = format!("stage=\"{}\",circuit=\"{circuit}\"", stage.name());
+            writeln!(
+                out,
+                "kroma_prover_stage_duration_seconds_sum{{{labels}}} {}",
+                durations.sum.as_secs_f64()
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "kroma_prover_stage_duration_seconds_count{{{labels}}} {}",
+                durations.count
+            )
+            .unwrap();
+        }
+        header(
+            &mut out,
+            "kroma_prover_stage_duration_max_seconds",
+            "Longest duration of the proving stages by circuit.",
+            "gauge",
+        );
+        for (stage, circuit, durations) in &stages {
+            writeln!(
+                out,
+                "kroma_prover_stage_duration_max_seconds{{stage=\"{}\",circuit=\"{circuit}\"}} {}",
+                stage.name(),
+                durations.max.as_secs_f64()
+            )
+            .unwrap();
+        }
+
+        header(
+            &mut out,
+            "kroma_prover_row_usage",
+            "Rows the proved batches used by sub circuit.",
+            "histogram",
+        );
+        let usable_rows = usable_rows() as f64;
+        for (sub_circuit, histogram) in PROVER_METRICS.row_usage() {
+            for (bound, count) in ROW_USAGE_BUCKETS.iter().zip(histogram.buckets) {
+                writeln!(
+                    out,
+                    "kroma_prover_row_usage_bucket{{sub_circuit=\"{sub_circuit}\",le=\"{}\"}} {count}",
+                    (bound * usable_rows) as u64
+                )
+                .unwrap();
+            }
+            writeln!(
+                out,
+                "kroma_prover_row_usage_bucket{{sub_circuit=\"{sub_circuit}\",le=\"+Inf\"}} {}",
+                histogram.count
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "kroma_prover_row_usage_sum{{sub_circuit=\"{sub_circuit}\"}} {}",
+                histogram.sum
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "kroma_prover_row_usage_count{{sub_circuit=\"{sub_circuit}\"}} {}",
+                histogram.count
+            )
+            .unwrap();
+        }
+        out
+    }
+}
+
+fn header(out: &mut String, metric: &str, help: &str, kind: &str) {
+    writeln!(out, "# HELP {metric} {help}").unwrap();
+    writeln!(out, "# TYPE {metric} {kind}").unwrap();
+}
+
+/// A proof counted by `ServerMetrics::start_proof`, finished when dropped.
+pub struct ProofInFlight {
+    metrics: &'static ServerMetrics,
+    proof_type: String,
+    succeeded: bool,
+}
+
+impl ProofInFlight {
+    pub fn succeed(mut self) {
+        self.succeeded = true;
+    }
+}
+
+impl Drop for ProofInFlight {
+    fn drop(&mut self) {
+        self.metrics.queue_depth.fetch_sub(1, Ordering::SeqCst);
+        let mut proofs = self.metrics.proofs.lock().unwrap();
+        let counts = proofs.entry(self.proof_type.clone()).or_default();
+        if self.succeeded {
+            counts.succeeded += 1;
+        } else {
+            counts.failed += 1;
+        }
+    }
+}
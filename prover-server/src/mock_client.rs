@@ -1,4 +1,5 @@
 pub mod prove;
+pub mod registry;
 pub mod spec;
 pub mod utils;
 
@@ -6,12 +7,18 @@ use crate::prove::ProofResult;
 use crate::spec::{ProofType, ZkSpec};
 use crate::utils::kroma_info;
 use clap::Parser;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use jsonrpsee::http_client::{HttpClient, HttpClientBuilder};
 use jsonrpsee_core::client::ClientT;
 use jsonrpsee_core::rpc_params;
+use std::collections::BTreeMap;
 use std::fs;
+use std::io::Write;
 use std::time::Duration;
+use types::compact;
 use types::eth::BlockTrace;
+use zkevm::circuit::CapacityCheck;
 
 const CLIENT_TIMEOUT_SEC: u64 = 7200;
 const DEFAULT_RPC_SERVER_ENDPOINT: &str = "http://127.0.0.1:3030";
@@ -22,34 +29,88 @@ struct Args {
     #[clap(short, long = "prove")]
     prove: Option<i32>,
 
+    /// Prove by the name of the proof type instead, e.g. `zktrie`.
+    #[clap(long = "circuit")]
+    circuit: Option<String>,
+
     #[clap(short, long = "spec")]
     spec: Option<bool>,
+
+    /// Vk hash the spec request expects, as `name:hash`, e.g. `super:<hex>` or `agg:<hex>`.
+    #[clap(long = "expected-vk")]
+    expected_vks: Vec<String>,
+
+    /// Upload the trace gzip compressed.
+    #[clap(long = "compressed")]
+    compressed: bool,
+
+    /// Upload the trace in the compact binary format.
+    #[clap(long = "compact")]
+    compact: bool,
+
+    /// Check the capacity for the trace.
+    #[clap(long = "check-capacity")]
+    check_capacity: bool,
 }
 
-async fn test_request_proof(cli: HttpClient, proof_type: ProofType) -> bool {
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Json,
+    Gzip,
+    Compact,
+}
+
+async fn test_request_proof(cli: HttpClient, proof_type: ProofType, encoding: Encoding) -> bool {
     let trace_str =
         fs::read_to_string("zkevm/tests/traces/kroma/multiple_transfers_0.json").unwrap();
     let trace: BlockTrace = serde_json::from_str(&trace_str).unwrap();
 
+    let method = if encoding == Encoding::Json {
+        "prove"
+    } else {
+        "prove_compressed"
+    };
     kroma_info(format!(
-        "Send 'prove' request: height({}), proof_type({proof_type})",
+        "Send '{method}' request: height({}), proof_type({proof_type})",
         trace.header.number.unwrap()
     ));
 
+    let trace_str = match encoding {
+        Encoding::Json => trace_str,
+        Encoding::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(trace_str.as_bytes()).unwrap();
+            base64::encode(encoder.finish().unwrap())
+        }
+        Encoding::Compact => base64::encode(compact::encode_block_trace(&trace).unwrap()),
+    };
     let params = rpc_params![trace_str, proof_type.to_value()];
-    let proof_result: ProofResult = cli.request("prove", params).await.unwrap();
+    let proof_result: ProofResult = cli.request(method, params).await.unwrap();
 
     kroma_info(format!(
-        "Got:\n - final_pair: {:?}\n - proof: {:?}",
-        proof_result.final_pair, proof_result.proof
+        "Got:\n - final_pair: {:?}\n - proof: {:?}\n - profile: {}",
+        proof_result.final_pair,
+        proof_result.proof,
+        serde_json::to_string_pretty(&proof_result.profile).unwrap()
     ));
 
     true
 }
 
-async fn test_request_spec(cli: HttpClient) -> bool {
+async fn test_request_spec(cli: HttpClient, expected_vks: &[String]) -> bool {
     kroma_info("Send 'spec' request to prover-server");
-    let params = rpc_params![];
+    let expected_vk_hashes = (!expected_vks.is_empty()).then(|| {
+        expected_vks
+            .iter()
+            .map(|vk| {
+                let (name, hash) = vk
+                    .split_once(':')
+                    .unwrap_or_else(|| panic!("expected vk {vk} is not name:hash"));
+                (name.to_string(), hash.to_string())
+            })
+            .collect::<BTreeMap<_, _>>()
+    });
+    let params = rpc_params![Option::<u64>::None, expected_vk_hashes];
     let zk_spec: ZkSpec = cli.request("spec", params).await.unwrap();
 
     kroma_info(format!(
@@ -59,13 +120,41 @@ async fn test_request_spec(cli: HttpClient) -> bool {
         \n - degree: {}\
         \n - chain_id: {}\
         \n - max_txs: {}\
-        \n - max_call_data: {}",
+        \n - max_call_data: {}\
+        \n - state_root_proving: {}\
+        \n - version: {}\
+        \n - circuits_rev: {}\
+        \n - vk_hashes: {:?} ({:?})\
+        \n - sub_circuits: {:?}\
+        \n - capacity: {:?}",
         zk_spec.proof_type_desc,
         zk_spec.agg_degree,
         zk_spec.degree,
         zk_spec.chain_id,
         zk_spec.max_txs,
-        zk_spec.max_call_data
+        zk_spec.max_call_data,
+        zk_spec.state_root_proving,
+        zk_spec.version,
+        zk_spec.circuits_rev,
+        zk_spec.vk_hashes,
+        zk_spec.vk_hashes_status,
+        zk_spec.sub_circuits,
+        zk_spec.capacity
+    ));
+
+    true
+}
+
+async fn test_request_check_capacity(cli: HttpClient) -> bool {
+    let trace_str =
+        fs::read_to_string("zkevm/tests/traces/kroma/multiple_transfers_0.json").unwrap();
+    kroma_info("Send 'check_capacity' request to prover-server");
+    let params = rpc_params![trace_str, Option::<Vec<usize>>::None];
+    let check: CapacityCheck = cli.request("check_capacity", params).await.unwrap();
+
+    kroma_info(format!(
+        "Got:\n - accepted: {}\n - bottleneck: {}\n - usage: {:?}\n - headroom: {:?}",
+        check.accepted, check.bottleneck, check.usage, check.headroom
     ));
 
     true
@@ -83,10 +172,24 @@ async fn main() {
         .unwrap();
 
     if args.spec.is_some() {
-        let _ = test_request_spec(http_client.clone()).await;
+        let _ = test_request_spec(http_client.clone(), &args.expected_vks).await;
+    }
+    if args.check_capacity {
+        let _ = test_request_check_capacity(http_client.clone()).await;
     }
-    if args.prove.is_some() {
-        let proof_type = ProofType::from_value(args.prove.expect("The proof type is not allowed."));
-        let _ = test_request_proof(http_client, proof_type).await;
+    let proof_type = match (args.prove, args.circuit.as_deref()) {
+        (Some(value), _) => Some(ProofType::from_value(value)),
+        (None, Some(name)) => Some(ProofType::from_name(name)),
+        (None, None) => None,
+    };
+    if let Some(proof_type) = proof_type {
+        let encoding = if args.compact {
+            Encoding::Compact
+        } else if args.compressed {
+            Encoding::Gzip
+        } else {
+            Encoding::Json
+        };
+        let _ = test_request_proof(http_client, proof_type, encoding).await;
     }
 }
@@ -1,3 +1,4 @@
+use crate::registry::ChainConfig;
 use crate::spec::ProofType;
 use crate::utils::{kroma_info, kroma_msg};
 use jsonrpc_core::Result;
@@ -6,91 +7,101 @@ use rand_xorshift::XorShiftRng;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::fs::create_dir_all;
-use std::io::Write;
 use std::path::{Path, PathBuf};
 use types::eth::BlockTrace;
 use utils::Measurer;
-use zkevm::circuit::{EvmCircuit, StateCircuit, SuperCircuit, AGG_DEGREE, DEGREE};
-use zkevm::io::write_file;
+use zkevm::circuit::{CircuitEntry, AGG_DEGREE, DEGREE};
+use zkevm::io::{write_file, write_file_atomically};
+use zkevm::metrics::ProofProfile;
 use zkevm::prover::{AggCircuitProof, Prover, TargetCircuitProof};
 use zkevm::utils::{load_kzg_params, load_or_create_seed};
 
-const PARAMS_DIR: &str = "./kzg_params/";
-const SEED_FILE: &str = "./rng_seed";
-const OUT_PROOF_DIR: &str = "./out_proof/";
 const VERIFIER_NAME: &str = "zk-verifier.sol";
+const PROFILE_NAME: &str = "profile.json";
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct ProofResult {
     pub final_pair: Option<Vec<u8>>,
     pub proof: Vec<u8>,
+    #[serde(default)]
+    pub profile: ProofProfile,
 }
 
 impl ProofResult {
     pub fn new(proof: Vec<u8>, final_pair: Option<Vec<u8>>) -> Self {
-        Self { proof, final_pair }
+        Self {
+            proof,
+            final_pair,
+            profile: Default::default(),
+        }
     }
 }
 
-pub fn create_proof(trace: BlockTrace, proof_type: ProofType) -> Result<ProofResult> {
+/// create the proof with the params, seed and out dir of the chain. The stages are profiled on
+/// top of `profile`, the profile of the request so far.
+pub fn create_proof(
+    trace: BlockTrace,
+    proof_type: ProofType,
+    chain: &ChainConfig,
+    profile: ProofProfile,
+) -> Result<ProofResult> {
     // load or create material for prover
-    let params = load_kzg_params(PARAMS_DIR, *DEGREE)
+    let params = load_kzg_params(&chain.params_dir, *DEGREE)
         .unwrap_or_else(|_| panic!("{}", kroma_msg("failed to load kzg params")));
-    let agg_params = load_kzg_params(PARAMS_DIR, *AGG_DEGREE)
+    let agg_params = load_kzg_params(&chain.params_dir, *AGG_DEGREE)
         .unwrap_or_else(|_| panic!("{}", kroma_msg("failed to load kzg agg params")));
-    let seed = load_or_create_seed(SEED_FILE)
+    let seed = load_or_create_seed(&chain.seed_file)
         .unwrap_or_else(|_| panic!("{}", kroma_msg("failed to load or create seed")));
     let rng = XorShiftRng::from_seed(seed);
 
     // prepare directory to store proof. (i.e., ./out_proof/<block_number>/)
     let height_hex = trace.header.number.unwrap().to_string();
-    let out_dir = PathBuf::from(OUT_PROOF_DIR).join(height_hex);
+    let out_dir = PathBuf::from(&chain.out_proof_dir).join(height_hex);
     let _ = create_dir_all(&out_dir);
 
     // build prover
     let mut prover = Prover::from_params_and_rng(params, agg_params, rng);
     // specify the dir to store the vk and proof of the intermediate circuit.
     prover.debug_dir = out_dir.to_str().unwrap().to_string();
+    prover.profile = profile;
+    prover.max_rows = chain.capacity.max_rows;
 
     match proof_type {
         ProofType::None => {
             panic!("invalid proof type");
         }
         ProofType::Agg => create_agg_proof(prover, trace),
-        _ => create_target_proof(prover, trace, proof_type),
+        ProofType::Target(circuit) => create_target_proof(prover, trace, circuit),
     }
 }
 
 pub fn create_target_proof(
     mut prover: Prover,
     trace: BlockTrace,
-    proof_type: ProofType,
+    circuit: &CircuitEntry,
 ) -> Result<ProofResult> {
     kroma_info("start creating proof");
 
     // generate proof
     let mut timer = Measurer::new();
-    let proof = match proof_type {
-        ProofType::Evm => prover
-            .create_target_circuit_proof::<EvmCircuit>(&trace)
-            .unwrap_or_else(|_| panic!("{}", kroma_msg("cannot generate evm_proof"))),
-        ProofType::State => prover
-            .create_target_circuit_proof::<StateCircuit>(&trace)
-            .unwrap_or_else(|_| panic!("{}", kroma_msg("cannot generate state_proof"))),
-        ProofType::Super => prover
-            .create_target_circuit_proof::<SuperCircuit>(&trace)
-            .unwrap_or_else(|_| panic!("{}", kroma_msg("cannot generate super_proof"))),
-        _ => {
-            panic!("invalid proof type");
-        }
-    };
+    let proof = circuit
+        .prove(&mut prover, std::slice::from_ref(&trace))
+        .unwrap_or_else(|_| {
+            panic!(
+                "{}",
+                kroma_msg(format!("cannot generate {}_proof", circuit.name))
+            )
+        });
     timer.end(&kroma_msg("finish generating a proof"));
 
-    // store the proof as a file
+    // store the proof and its profile as files
     let proof_dir = PathBuf::from(&prover.debug_dir);
-    write_target_proof(&proof_dir, proof.clone(), &proof_type.to_string());
+    write_target_proof(&proof_dir, proof.clone(), &circuit.name);
+    let profile = prover.take_profile();
+    write_profile(&proof_dir, &profile);
 
-    let proof_result = ProofResult::new(proof.proof, None);
+    let mut proof_result = ProofResult::new(proof.proof, None);
+    proof_result.profile = profile;
     Ok(proof_result)
 }
 
@@ -104,20 +115,22 @@ pub fn create_agg_proof(mut prover: Prover, trace: BlockTrace) -> Result<ProofRe
         .unwrap_or_else(|_| panic!("{}", kroma_msg("cannot generate agg_proof")));
     timer.end(&kroma_msg("finish generating a proof"));
 
-    // store proof and verifier contract as files
+    // store proof, verifier contract and profile as files
     let dir = PathBuf::from(prover.debug_dir.clone());
     write_agg_proof(&dir, &proof);
     write_solidity(&prover, &proof, &dir, VERIFIER_NAME);
+    let profile = prover.take_profile();
+    write_profile(&dir, &profile);
     kroma_info(format!("output files to {}", dir.to_str().unwrap()));
 
-    let proof_result = ProofResult::new(proof.proof.clone(), Some(proof.final_pair));
+    let mut proof_result = ProofResult::new(proof.proof.clone(), Some(proof.final_pair));
+    proof_result.profile = profile;
     Ok(proof_result)
 }
 
 pub fn write_target_proof(dir: &Path, proof: TargetCircuitProof, proof_type: &str) {
     let proof_path = dir.join(proof_type.to_string() + ".proof");
-    let mut f = fs::File::create(proof_path).unwrap();
-    f.write_all(proof.proof.as_slice()).unwrap();
+    write_file_atomically(&proof_path, &proof.proof).unwrap();
 }
 
 pub fn write_agg_proof(dir: &Path, proof: &AggCircuitProof) {
@@ -127,6 +140,16 @@ pub fn write_agg_proof(dir: &Path, proof: &AggCircuitProof) {
     proof.write_to_dir(&mut proof_path);
 }
 
+pub fn write_profile(dir: &Path, profile: &ProofProfile) {
+    kroma_info(format!(
+        "proof took {:.1}s, peak rss {}MB",
+        profile.wall_secs(),
+        profile.peak_rss_bytes() / 1024 / 1024
+    ));
+    let buf = serde_json::to_vec_pretty(profile).unwrap();
+    write_file_atomically(&dir.join(PROFILE_NAME), &buf).unwrap();
+}
+
 pub fn write_solidity(
     prover: &Prover,
     proof: &AggCircuitProof,
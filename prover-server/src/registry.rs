@@ -0,0 +1,185 @@
+use anyhow::{bail, Context};
+use once_cell::sync::Lazy;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use zkevm::circuit::{usable_rows, AGG_DEGREE, CHAIN_ID, DEGREE, MAX_TXS};
+use zkevm::utils::read_env_var;
+
+const DEFAULT_PARAMS_DIR: &str = "./kzg_params/";
+const DEFAULT_SEED_FILE: &str = "./rng_seed";
+const DEFAULT_OUT_PROOF_DIR: &str = "./out_proof/";
+
+/// Chains served by the process, loaded from the file `CHAIN_REGISTRY` points to, or the single
+/// chain `CHAIN_ID` if it is not set.
+pub static CHAIN_REGISTRY: Lazy<ChainRegistry> = Lazy::new(|| {
+    let path: String = read_env_var("CHAIN_REGISTRY", String::new());
+    if path.is_empty() {
+        ChainRegistry::single(*CHAIN_ID)
+    } else {
+        ChainRegistry::load(&path)
+            .unwrap_or_else(|e| panic!("failed to load chain registry {path}: {e:?}"))
+    }
+});
+
+/// Limits of the blocks proved for a chain, within the limits of the circuits.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CapacityProfile {
+    #[serde(default = "default_max_txs")]
+    pub max_txs: usize,
+    /// rows each sub circuit may use.
+    #[serde(default = "usable_rows")]
+    pub max_rows: usize,
+}
+
+fn default_max_txs() -> usize {
+    MAX_TXS
+}
+
+impl Default for CapacityProfile {
+    fn default() -> Self {
+        Self {
+            max_txs: MAX_TXS,
+            max_rows: usable_rows(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainConfig {
+    pub chain_id: u64,
+    #[serde(default)]
+    pub name: String,
+    /// dir of the kzg params of `DEGREE` and `AGG_DEGREE`.
+    #[serde(default = "default_params_dir")]
+    pub params_dir: String,
+    #[serde(default = "default_seed_file")]
+    pub seed_file: String,
+    /// dir the proofs and the verifier contract are written into.
+    #[serde(default = "default_out_proof_dir")]
+    pub out_proof_dir: String,
+    /// vk of the agg circuit the proofs are checked against, e.g. the `verify_circuit.vkey`
+    /// of an agg proof. Its hash is reported by `spec`.
+    #[serde(default)]
+    pub agg_vk_file: Option<String>,
+    /// degrees the keys are made for, checked against the degrees the server runs with.
+    pub degree: Option<usize>,
+    pub agg_degree: Option<usize>,
+    #[serde(default)]
+    pub capacity: CapacityProfile,
+}
+
+fn default_params_dir() -> String {
+    DEFAULT_PARAMS_DIR.to_string()
+}
+
+fn default_seed_file() -> String {
+    DEFAULT_SEED_FILE.to_string()
+}
+
+fn default_out_proof_dir() -> String {
+    DEFAULT_OUT_PROOF_DIR.to_string()
+}
+
+impl ChainConfig {
+    fn new(chain_id: u64) -> Self {
+        Self {
+            chain_id,
+            name: String::new(),
+            params_dir: default_params_dir(),
+            seed_file: default_seed_file(),
+            out_proof_dir: default_out_proof_dir(),
+            agg_vk_file: None,
+            degree: None,
+            agg_degree: None,
+            capacity: Default::default(),
+        }
+    }
+
+    fn check(&self) -> anyhow::Result<()> {
+        // the circuits are sized when the server is built, not per chain
+        if let Some(degree) = self.degree.filter(|d| *d != *DEGREE) {
+            bail!("degree {degree} is not the DEGREE {}", *DEGREE);
+        }
+        if let Some(agg_degree) = self.agg_degree.filter(|d| *d != *AGG_DEGREE) {
+            bail!(
+                "agg degree {agg_degree} is not the AGG_DEGREE {}",
+                *AGG_DEGREE
+            );
+        }
+        if let Some(file) = self
+            .agg_vk_file
+            .as_ref()
+            .filter(|f| !Path::new(f).is_file())
+        {
+            bail!("agg vk file {file} does not exist");
+        }
+        if self.capacity.max_txs > MAX_TXS {
+            bail!(
+                "max txs {} exceeds MAX_TXS {MAX_TXS}",
+                self.capacity.max_txs
+            );
+        }
+        if self.capacity.max_rows > usable_rows() {
+            bail!(
+                "max rows {} exceeds the usable rows {}",
+                self.capacity.max_rows,
+                usable_rows()
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Chain configs by chain ID. Requests are served with the config of the chain of their trace.
+#[derive(Debug, Clone)]
+pub struct ChainRegistry {
+    chains: BTreeMap<u64, ChainConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RegistryFile {
+    chains: Vec<ChainConfig>,
+}
+
+impl ChainRegistry {
+    /// registry of only the chain, with the default paths and capacity.
+    pub fn single(chain_id: u64) -> Self {
+        Self {
+            chains: BTreeMap::from([(chain_id, ChainConfig::new(chain_id))]),
+        }
+    }
+
+    /// load a JSON file of `{"chains": [ChainConfig, ..]}`.
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let file: RegistryFile = serde_json::from_slice(&fs::read(path)?)?;
+        let mut chains = BTreeMap::new();
+        for chain in file.chains {
+            chain
+                .check()
+                .with_context(|| format!("invalid config of chain {}", chain.chain_id))?;
+            let chain_id = chain.chain_id;
+            if chains.insert(chain_id, chain).is_some() {
+                bail!("chain {chain_id} is registered twice");
+            }
+        }
+        if chains.is_empty() {
+            bail!("no chain");
+        }
+        Ok(Self { chains })
+    }
+
+    pub fn get(&self, chain_id: u64) -> Option<&ChainConfig> {
+        self.chains.get(&chain_id)
+    }
+
+    pub fn chain_ids(&self) -> Vec<u64> {
+        self.chains.keys().copied().collect()
+    }
+
+    /// the chain of a request not telling its chain, the only one or the lowest chain ID.
+    pub fn default_chain(&self) -> &ChainConfig {
+        self.chains.values().next().expect("no chain")
+    }
+}
@@ -1,18 +1,32 @@
+mod health;
+mod metrics;
 mod prove;
+mod registry;
 mod spec;
 pub mod utils;
 
+use crate::health::{wait_for_shutdown_signal, Job, HEALTH_PATH, READY_PATH, SERVER_STATE};
+use crate::metrics::{METRICS_PATH, SERVER_METRICS};
 use crate::prove::{create_proof, ProofResult};
-use crate::spec::ProofType;
-use crate::utils::{kroma_err, kroma_info};
+use crate::registry::{ChainConfig, CHAIN_REGISTRY};
+use crate::spec::{load_vk_hashes, vk_hashes, ProofType, VkHashesError};
+use crate::utils::{decode_compressed_trace, kroma_err, kroma_info};
 use ::utils::check_chain_id;
 use clap::Parser;
 use jsonrpc_derive::rpc;
 use jsonrpc_http_server::jsonrpc_core::Result;
-use jsonrpc_http_server::ServerBuilder;
+use jsonrpc_http_server::{hyper, RequestMiddlewareAction, ServerBuilder};
 use spec::ZkSpec;
-use types::eth::BlockTrace;
-use zkevm::circuit::{CHAIN_ID, MAX_TXS};
+use std::collections::BTreeMap;
+use std::env;
+use types::eth::{block_trace_from_reader_skipping_memory, validate, BlockTrace};
+use zkevm::circuit::{
+    check_capacity, resolve_chain_id, usable_rows, CapacityCheck, SUB_CIRCUIT_NAMES,
+};
+use zkevm::metrics::{ProofProfile, ProveStage};
+
+const DEFAULT_MAX_REQUEST_BODY_SIZE: usize = 32_000_000;
+
 #[rpc]
 pub trait Rpc {
     #[rpc(name = "spec")]
@@ -26,14 +40,64 @@ pub trait Rpc {
     /// 3. pub chain_id: u32,
     /// 4. pub max_txs: u32,
     /// 5. pub max_call_data: u32,
-    fn spec(&self) -> Result<ZkSpec> {
-        let spec = ZkSpec::new(*CHAIN_ID as u32);
+    /// 6. pub chain_ids: Vec<u64>,
+    /// 7. pub state_root_proving: bool,
+    /// 8. pub version: String,
+    /// 9. pub circuits_rev: String,
+    /// 10. pub vk_hashes: BTreeMap<String, String>,
+    /// 11. pub vk_hashes_status: VkHashesStatus,
+    /// 12. pub vk_hashes_error: Option<String>,
+    /// 13. pub sub_circuits: Vec<String>,
+    /// 14. pub capacity: CapacityProfile,
+    ///
+    /// of the chain, or of the default chain if not given. The vk hashes are the ones loaded so
+    /// far, see `vk_hashes_status`. If `expected_vk_hashes` is given, a client fails fast with an
+    /// invalid params error carrying the actual hashes unless every expected vk hash is the one
+    /// of the server, or with an internal error while an expected hash is not loaded.
+    fn spec(
+        &self,
+        chain_id: Option<u64>,
+        expected_vk_hashes: Option<BTreeMap<String, String>>,
+    ) -> Result<ZkSpec> {
+        let chain = match chain_id {
+            Some(chain_id) => registered_chain(chain_id)?,
+            None => CHAIN_REGISTRY.default_chain(),
+        };
+        let spec = ZkSpec::new(chain, vk_hashes(chain));
+        if let Err(e) = spec.check_vk_hashes(&expected_vk_hashes.unwrap_or_default()) {
+            let msg = match &spec.vk_hashes_error {
+                Some(error) => format!("{e}, {error}"),
+                None => e.to_string(),
+            };
+            kroma_err(&msg);
+            let code = match e {
+                VkHashesError::NotLoaded { .. } => jsonrpc_core::ErrorCode::InternalError,
+                VkHashesError::Mismatch(_) => jsonrpc_core::ErrorCode::InvalidParams,
+            };
+            return Err(jsonrpc_core::Error {
+                code,
+                message: msg,
+                data: serde_json::to_value(&spec.vk_hashes).ok(),
+            });
+        }
         Ok(spec)
     }
 
     #[rpc(name = "prove")]
-    /// return proof related to the trace.
+    /// return proof related to the trace. The request body is capped by `--max-body-size`,
+    /// large traces are better uploaded through `prove_compressed`.
     fn prove(&self, trace: String, proof_type: i32) -> Result<ProofResult>;
+
+    #[rpc(name = "prove_compressed")]
+    /// return proof related to the trace, which is uploaded as base64 encoded gzip JSON
+    /// or compact binary trace. The request body is buffered like any JSON-RPC request and
+    /// capped by `--max-body-size`, the trace is decoded from it as a stream.
+    fn prove_compressed(&self, trace: String, proof_type: i32) -> Result<ProofResult>;
+
+    #[rpc(name = "check_capacity")]
+    /// return the row usage of the trace on top of `usage`, the usage accumulated so far per
+    /// sub circuit, and whether it still fits into a proof.
+    fn check_capacity(&self, trace: String, usage: Option<Vec<usize>>) -> Result<CapacityCheck>;
 }
 
 pub struct RpcImpl;
@@ -43,25 +107,20 @@ impl Rpc for RpcImpl {
     ///
     /// # Arguments
     /// * `trace` - A trace of the specific block as a JSON String.
-    /// * `proof_type` - An identifier of proof type (1: Evm, 2: State, 3: Super, 4: Agg)
+    /// * `proof_type` - An identifier of proof type, see `proof_type_desc` of the spec, which
+    ///   lists the circuits of `CIRCUIT_REGISTRY` and the agg proof.
     ///
     /// # Returns
     /// ProofResult instance which includes proof and final pair.
     fn prove(&self, trace: String, proof_type_val: i32) -> Result<ProofResult> {
-        // initiate ProofType
-        let proof_type = ProofType::from_value(proof_type_val);
-        if let ProofType::None = proof_type {
-            let msg = format!(
-                "invalid prove param: expected param from 1 to 4, but {:?}",
-                proof_type_val
-            );
-            kroma_err(&msg);
-            let err = jsonrpc_core::Error::invalid_params(msg);
-            return Err(err);
-        }
-
         // initiate BlockTrace
-        let block_trace: BlockTrace = match serde_json::from_slice(trace.as_bytes()) {
+        let mut profile = ProofProfile::default();
+        let parsed = profile.measure(
+            ProveStage::TraceParse,
+            &proof_type_name(proof_type_val),
+            || block_trace_from_reader_skipping_memory(trace.as_bytes()),
+        );
+        let block_trace: BlockTrace = match parsed {
             Ok(trace) => trace,
             Err(_) => {
                 kroma_err("invalid block trace.");
@@ -69,33 +128,199 @@ impl Rpc for RpcImpl {
                 return Err(err);
             }
         };
+        // release the raw request before the long running proof.
+        drop(trace);
 
-        // check number of txs in the trace
-        let tx_count = block_trace.transactions.len();
-        if tx_count > MAX_TXS {
-            let msg = format!(
-                "too many transactions. MAX_TXS: {}, given transactions: {}",
-                MAX_TXS, tx_count
-            );
-            kroma_err(&msg);
-            let err = jsonrpc_core::Error::invalid_params(msg);
-            return Err(err);
-        }
+        prove_block_trace(block_trace, proof_type_val, profile)
+    }
+
+    /// same as `prove`, but `trace` is a base64 encoded gzip JSON.
+    fn prove_compressed(&self, trace: String, proof_type_val: i32) -> Result<ProofResult> {
+        let mut profile = ProofProfile::default();
+        let decoded = profile.measure(
+            ProveStage::TraceParse,
+            &proof_type_name(proof_type_val),
+            || decode_compressed_trace(&trace),
+        );
+        let block_trace = match decoded {
+            Ok(trace) => trace,
+            Err(e) => {
+                kroma_err(format!("invalid compressed block trace: {e}"));
+                let err = jsonrpc_core::Error::invalid_params("invalid format trace");
+                return Err(err);
+            }
+        };
+        drop(trace);
+
+        prove_block_trace(block_trace, proof_type_val, profile)
+    }
 
-        // check chain id
-        let trace_chain_id = block_trace.chain_id;
-        if *CHAIN_ID != trace_chain_id.as_u64() {
-            let msg = format!(
-                "not matched chain ids: expected({:?}), requested({:?})",
-                *CHAIN_ID, trace_chain_id
-            );
+    /// check the capacity for a trace of a block, or of the txs to add to a block. Traces with
+    /// `validate` issues are rejected with the issues, as by `prove`.
+    ///
+    /// # Arguments
+    /// * `trace` - A trace as a JSON String.
+    /// * `usage` - Rows used so far per sub circuit, in the order of `sub_circuits` of the result.
+    ///
+    /// # Returns
+    /// CapacityCheck instance which includes row usage, headroom and the decision.
+    fn check_capacity(&self, trace: String, usage: Option<Vec<usize>>) -> Result<CapacityCheck> {
+        let block_trace: BlockTrace =
+            match block_trace_from_reader_skipping_memory(trace.as_bytes()) {
+                Ok(trace) => trace,
+                Err(_) => {
+                    kroma_err("invalid block trace.");
+                    let err = jsonrpc_core::Error::invalid_params("invalid format trace");
+                    return Err(err);
+                }
+            };
+        drop(trace);
+
+        let chain = chain_of(&block_trace)?;
+        check_trace(&block_trace)?;
+        let _job = accept_job()?;
+        check_capacity(
+            &block_trace,
+            &usage.unwrap_or_default(),
+            chain.capacity.max_rows,
+        )
+        .map_err(|e| {
+            let msg = format!("failed to check capacity: {e}");
             kroma_err(&msg);
-            let err = jsonrpc_core::Error::invalid_params(msg);
-            return Err(err);
+            jsonrpc_core::Error::invalid_params(msg)
+        })
+    }
+}
+
+/// start a job the shutdown waits for, fails once the server is shutting down.
+fn accept_job() -> Result<Job> {
+    SERVER_STATE.accept_job().ok_or_else(|| {
+        let msg = "server is shutting down";
+        kroma_err(msg);
+        jsonrpc_core::Error {
+            code: jsonrpc_core::ErrorCode::ServerError(-32000),
+            message: msg.to_string(),
+            data: None,
         }
+    })
+}
+
+/// reject a trace which `validate` finds issues in, with the issues as data.
+fn check_trace(block_trace: &BlockTrace) -> Result<()> {
+    let issues = validate(block_trace);
+    if issues.is_empty() {
+        return Ok(());
+    }
+    let msg = format!("invalid block trace: {} issues", issues.len());
+    kroma_err(format!(
+        "{msg}\n{}",
+        issues
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n")
+    ));
+    Err(jsonrpc_core::Error {
+        code: jsonrpc_core::ErrorCode::InvalidParams,
+        message: msg,
+        data: serde_json::to_value(&issues).ok(),
+    })
+}
+
+fn prove_block_trace(
+    block_trace: BlockTrace,
+    proof_type_val: i32,
+    profile: ProofProfile,
+) -> Result<ProofResult> {
+    // initiate ProofType
+    let proof_type = ProofType::from_value(proof_type_val);
+    if let ProofType::None = proof_type {
+        let msg = format!(
+            "invalid prove param: expected one of {:?}, but {:?}",
+            ProofType::desc(),
+            proof_type_val
+        );
+        kroma_err(&msg);
+        let err = jsonrpc_core::Error::invalid_params(msg);
+        return Err(err);
+    }
+
+    // check chain id
+    let chain = chain_of(&block_trace)?;
 
-        create_proof(block_trace, proof_type)
+    // check number of txs in the trace
+    let tx_count = block_trace.transactions.len();
+    if tx_count > chain.capacity.max_txs {
+        let msg = format!(
+            "too many transactions. MAX_TXS: {}, given transactions: {}",
+            chain.capacity.max_txs, tx_count
+        );
+        kroma_err(&msg);
+        let err = jsonrpc_core::Error::invalid_params(msg);
+        return Err(err);
     }
+
+    // reject malformed traces before they reach the witness generation
+    check_trace(&block_trace)?;
+
+    let _job = accept_job()?;
+    let in_flight = SERVER_METRICS.start_proof(proof_type.to_string());
+    let result = create_proof(block_trace, proof_type, chain, profile);
+    if result.is_ok() {
+        in_flight.succeed();
+    }
+    result
+}
+
+/// serve the metrics, liveness and readiness, the other requests go to the rpc.
+fn http_middleware(request: hyper::Request<hyper::Body>) -> RequestMiddlewareAction {
+    let (status, body) = match request.uri().path() {
+        METRICS_PATH => (hyper::StatusCode::OK, SERVER_METRICS.render()),
+        HEALTH_PATH => (hyper::StatusCode::OK, "ok".to_string()),
+        READY_PATH if SERVER_STATE.is_ready() => (hyper::StatusCode::OK, "ready".to_string()),
+        READY_PATH => (
+            hyper::StatusCode::SERVICE_UNAVAILABLE,
+            SERVER_STATE
+                .load_error()
+                .map(|e| format!("failed to load: {e}"))
+                .unwrap_or_else(|| "not ready".to_string()),
+        ),
+        _ => return request.into(),
+    };
+    hyper::Response::builder()
+        .status(status)
+        .header("Content-Type", "text/plain")
+        .body(body.into())
+        .unwrap()
+        .into()
+}
+
+/// name of the proof type a stage is profiled for, before the proof type is checked.
+fn proof_type_name(proof_type_val: i32) -> String {
+    ProofType::from_value(proof_type_val).to_string()
+}
+
+/// return the config of the chain, an error for the request if it is not registered.
+fn registered_chain(chain_id: u64) -> Result<&'static ChainConfig> {
+    CHAIN_REGISTRY.get(chain_id).ok_or_else(|| {
+        let msg = format!(
+            "not registered chain id: expected one of {:?}, requested({})",
+            CHAIN_REGISTRY.chain_ids(),
+            chain_id
+        );
+        kroma_err(&msg);
+        jsonrpc_core::Error::invalid_params(msg)
+    })
+}
+
+/// return the config of the chain of the trace, see `resolve_chain_id`.
+fn chain_of(block_trace: &BlockTrace) -> Result<&'static ChainConfig> {
+    let chain_id = resolve_chain_id(std::slice::from_ref(block_trace)).map_err(|e| {
+        let msg = format!("invalid chain id: {e}");
+        kroma_err(&msg);
+        jsonrpc_core::Error::invalid_params(msg)
+    })?;
+    registered_chain(chain_id.as_u64())
 }
 
 pub struct MockRpcImpl;
@@ -106,6 +331,30 @@ impl Rpc for MockRpcImpl {
         kroma_info("return zero proof");
         Ok(ProofResult::new(vec![0; 4640], Some(vec![0; 128])))
     }
+
+    fn prove_compressed(&self, trace: String, proof_type: i32) -> Result<ProofResult> {
+        self.prove(trace, proof_type)
+    }
+
+    /// Regardless of the received trace, it accepts with no rows used.
+    fn check_capacity(&self, _trace: String, usage: Option<Vec<usize>>) -> Result<CapacityCheck> {
+        kroma_info("return empty capacity check");
+        let sub_circuits: Vec<String> = SUB_CIRCUIT_NAMES.iter().map(|n| n.to_string()).collect();
+        let usage = usage.unwrap_or_else(|| vec![0; sub_circuits.len()]);
+        let usable_rows = usable_rows();
+        Ok(CapacityCheck {
+            trace_usage: vec![0; sub_circuits.len()],
+            headroom: usage
+                .iter()
+                .map(|rows| usable_rows as i64 - *rows as i64)
+                .collect(),
+            bottleneck: sub_circuits[0].clone(),
+            sub_circuits,
+            usage,
+            usable_rows,
+            accepted: true,
+        })
+    }
 }
 
 #[derive(Parser, Debug)]
@@ -113,13 +362,21 @@ impl Rpc for MockRpcImpl {
 struct Args {
     #[clap(short, long = "endpoint")]
     endpoint: Option<String>,
+
+    /// Max size of a request body in bytes.
+    #[clap(long = "max-body-size", default_value_t = DEFAULT_MAX_REQUEST_BODY_SIZE)]
+    max_body_size: usize,
 }
 
 fn main() {
     dotenv::dotenv().ok();
     env_logger::init();
 
-    let chain_id = check_chain_id();
+    let chain_ids = if env::var("CHAIN_REGISTRY").map_or(true, |v| v.is_empty()) {
+        check_chain_id()
+    } else {
+        format!("{:?}", CHAIN_REGISTRY.chain_ids())
+    };
     let args = Args::parse();
     let endpoint = args.endpoint.unwrap_or("127.0.0.1:3030".to_string());
 
@@ -129,14 +386,46 @@ fn main() {
     #[cfg(feature = "mock-server")]
     io.extend_with(MockRpcImpl.to_delegate());
 
+    // the params and keys, e.g. for the vk hashes of `spec`, take a while to load, so they are
+    // loaded in the background and the server is ready once they are. A failure is reported by
+    // `READY_PATH` and `spec` instead.
+    std::thread::spawn(|| {
+        let mut errors = vec![];
+        for chain_id in CHAIN_REGISTRY.chain_ids() {
+            let chain = CHAIN_REGISTRY.get(chain_id).unwrap();
+            if let Err(e) = load_vk_hashes(chain) {
+                kroma_err(format!("failed to get vk hashes of {chain_id}: {e}"));
+                errors.push(format!("chain {chain_id}: {e}"));
+            }
+        }
+        if errors.is_empty() {
+            SERVER_STATE.set_ready();
+            kroma_info("Prover server ready");
+        } else {
+            SERVER_STATE.set_load_failed(errors.join("; "));
+        }
+    });
+
     kroma_info(format!(
-        "Prover server starting on {endpoint}. CHAIN_ID: {chain_id}"
+        "Prover server starting on {endpoint}. CHAIN_ID: {chain_ids}"
     ));
     let server = ServerBuilder::new(io)
         .threads(3)
-        .max_request_body_size(32_000_000)
+        .max_request_body_size(args.max_body_size)
+        .request_middleware(http_middleware)
         .start_http(&endpoint.parse().unwrap())
         .unwrap();
 
+    // on SIGTERM, finish the proofs in flight before closing, so that no half-written proof is
+    // left behind.
+    let close_handle = server.close_handle();
+    std::thread::spawn(move || {
+        wait_for_shutdown_signal();
+        kroma_info("Prover server shutting down, no more proofs are accepted");
+        SERVER_STATE.shutdown();
+        close_handle.close();
+    });
+
     server.wait();
+    kroma_info("Prover server stopped");
 }
@@ -1,26 +1,34 @@
-use enum_iterator::all;
-use enum_iterator::Sequence;
+use crate::registry::{CapacityProfile, ChainConfig, CHAIN_REGISTRY};
+use anyhow::anyhow;
+use once_cell::sync::Lazy;
 use serde_derive::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use std::fmt::Display;
-use zkevm::circuit::{AGG_DEGREE, CHAIN_ID, DEGREE, MAX_CALLDATA, MAX_TXS};
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Mutex, PoisonError};
+use zkevm::circuit::{
+    CircuitEntry, AGG_DEGREE, AGG_PROOF_ID, CHAIN_ID, CIRCUIT_REGISTRY, DEGREE, MAX_CALLDATA,
+    MAX_TXS, STATE_ROOT_PROVING, SUB_CIRCUIT_NAMES, ZKEVM_CIRCUITS_REV,
+};
+use zkevm::io::serialize_vk;
+use zkevm::utils::{hash_bytes, load_kzg_params};
 
-#[derive(Debug, Sequence, Serialize, Deserialize)]
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The proof requested, of a circuit of `CIRCUIT_REGISTRY` or the agg proof.
+#[derive(Debug, Clone, Copy)]
 pub enum ProofType {
     None,
-    Evm,
-    State,
-    Super,
+    Target(&'static CircuitEntry),
     Agg,
 }
 
 impl Display for ProofType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            ProofType::Evm => write!(f, "evm"),
-            ProofType::State => write!(f, "state"),
-            ProofType::Super => write!(f, "super"),
+            ProofType::Target(circuit) => write!(f, "{}", circuit.name),
             ProofType::Agg => write!(f, "agg"),
             ProofType::None => write!(f, "none"),
         }
@@ -30,36 +38,41 @@ impl Display for ProofType {
 impl ProofType {
     /// select the enum by a value.
     pub fn from_value(val: i32) -> Self {
-        match val {
-            1 => ProofType::Evm,
-            2 => ProofType::State,
-            3 => ProofType::Super,
-            4 => ProofType::Agg,
-            _ => ProofType::None,
+        if val == AGG_PROOF_ID {
+            return ProofType::Agg;
         }
+        CIRCUIT_REGISTRY
+            .by_id(val)
+            .map_or(ProofType::None, ProofType::Target)
     }
 
     /// extract value related to the enum.
     pub fn to_value(&self) -> i32 {
         match self {
-            ProofType::Evm => 1,
-            ProofType::State => 2,
-            ProofType::Super => 3,
-            ProofType::Agg => 4,
+            ProofType::Target(circuit) => circuit.id,
+            ProofType::Agg => AGG_PROOF_ID,
             ProofType::None => 0,
         }
     }
 
-    /// returns enum-value mapping as a String.
-    pub fn desc() -> HashMap<String, i32> {
-        let mut mapping = HashMap::new();
-        let proof_type_vec = all::<ProofType>().collect::<Vec<_>>();
-        for i in proof_type_vec {
-            let key = i.to_string();
-            let value = i.to_value();
-            mapping.insert(key, value);
+    /// select the enum by its name, e.g. `super`.
+    pub fn from_name(name: &str) -> Self {
+        if name == "agg" {
+            return ProofType::Agg;
         }
-        mapping
+        CIRCUIT_REGISTRY
+            .get(name)
+            .map_or(ProofType::None, ProofType::Target)
+    }
+
+    /// returns name-value mapping of the proofs the server can create.
+    pub fn desc() -> HashMap<String, i32> {
+        CIRCUIT_REGISTRY
+            .entries()
+            .iter()
+            .map(|circuit| (circuit.name.clone(), circuit.id))
+            .chain(std::iter::once(("agg".to_string(), AGG_PROOF_ID)))
+            .collect()
     }
 }
 
@@ -71,6 +84,32 @@ pub struct ZkSpec {
     pub chain_id: u32,
     pub max_txs: u32,
     pub max_call_data: u32,
+    /// chains the server proves for.
+    #[serde(default)]
+    pub chain_ids: Vec<u64>,
+    /// whether the proofs cover the state root transition, see the `zktrie` feature.
+    #[serde(default)]
+    pub state_root_proving: bool,
+    /// version of the prover.
+    #[serde(default)]
+    pub version: String,
+    /// git rev of the zkevm-circuits the circuits are built from.
+    #[serde(default)]
+    pub circuits_rev: String,
+    /// hex digests of the vks by circuit name, `agg` for the agg circuit if its vk is configured.
+    #[serde(default)]
+    pub vk_hashes: BTreeMap<String, String>,
+    /// whether `vk_hashes` are complete, they are filled in while the server starts.
+    #[serde(default)]
+    pub vk_hashes_status: VkHashesStatus,
+    /// why the vk hashes failed to load.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vk_hashes_error: Option<String>,
+    /// sub circuits of the super circuit, in the order of the row usage of `check_capacity`.
+    #[serde(default)]
+    pub sub_circuits: Vec<String>,
+    #[serde(default)]
+    pub capacity: CapacityProfile,
 }
 
 impl Default for ZkSpec {
@@ -82,19 +121,230 @@ impl Default for ZkSpec {
             chain_id: *CHAIN_ID as u32,
             max_txs: MAX_TXS as u32,
             max_call_data: MAX_CALLDATA as u32,
+            chain_ids: vec![*CHAIN_ID],
+            state_root_proving: STATE_ROOT_PROVING,
+            version: VERSION.to_string(),
+            circuits_rev: ZKEVM_CIRCUITS_REV.to_string(),
+            vk_hashes: BTreeMap::new(),
+            vk_hashes_status: VkHashesStatus::Loaded,
+            vk_hashes_error: None,
+            sub_circuits: SUB_CIRCUIT_NAMES.iter().map(|n| n.to_string()).collect(),
+            capacity: Default::default(),
         }
     }
 }
 
 impl ZkSpec {
-    pub fn new(chain_id: u32) -> Self {
+    pub fn new(chain: &ChainConfig, vk_hashes: VkHashes) -> Self {
         Self {
             proof_type_desc: ProofType::desc(),
             degree: *DEGREE as u32,
             agg_degree: *AGG_DEGREE as u32,
-            chain_id,
-            max_txs: MAX_TXS as u32,
+            chain_id: chain.chain_id as u32,
+            max_txs: chain.capacity.max_txs as u32,
             max_call_data: MAX_CALLDATA as u32,
+            chain_ids: CHAIN_REGISTRY.chain_ids(),
+            state_root_proving: STATE_ROOT_PROVING,
+            version: VERSION.to_string(),
+            circuits_rev: ZKEVM_CIRCUITS_REV.to_string(),
+            vk_hashes: vk_hashes.hashes,
+            vk_hashes_status: vk_hashes.status,
+            vk_hashes_error: vk_hashes.error,
+            sub_circuits: SUB_CIRCUIT_NAMES.iter().map(|n| n.to_string()).collect(),
+            capacity: chain.capacity.clone(),
+        }
+    }
+
+    /// check the vk hashes against the expected ones. Expected hashes which are not loaded
+    /// fail only until the hashes are loaded, then they are mismatches.
+    pub fn check_vk_hashes(
+        &self,
+        expected: &BTreeMap<String, String>,
+    ) -> Result<(), VkHashesError> {
+        let missing: Vec<_> = expected
+            .keys()
+            .filter(|name| !self.vk_hashes.contains_key(*name))
+            .cloned()
+            .collect();
+        if !missing.is_empty() && self.vk_hashes_status != VkHashesStatus::Loaded {
+            return Err(VkHashesError::NotLoaded {
+                circuits: missing,
+                status: self.vk_hashes_status,
+            });
+        }
+        let mismatches: Vec<_> = expected
+            .iter()
+            .filter(|(name, hash)| self.vk_hashes.get(*name) != Some(*hash))
+            .map(|(name, _)| name.clone())
+            .collect();
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(VkHashesError::Mismatch(mismatches))
+        }
+    }
+}
+
+/// Progress of `load_vk_hashes` of a chain.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VkHashesStatus {
+    Loading,
+    // servers not reporting the status have loaded the hashes
+    #[default]
+    Loaded,
+    Failed,
+}
+
+/// The vk hashes of a chain loaded so far.
+#[derive(Debug, Clone, Default)]
+pub struct VkHashes {
+    pub status: VkHashesStatus,
+    pub hashes: BTreeMap<String, String>,
+    /// why the hashes failed to load.
+    pub error: Option<String>,
+}
+
+/// Expected vk hashes `spec` fails on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VkHashesError {
+    /// the hashes of the circuits are not loaded yet, or failed to load.
+    NotLoaded {
+        circuits: Vec<String>,
+        status: VkHashesStatus,
+    },
+    /// the hashes of the circuits are not the expected ones.
+    Mismatch(Vec<String>),
+}
+
+impl Display for VkHashesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotLoaded { circuits, status } => {
+                let reason = match status {
+                    VkHashesStatus::Failed => "failed to load",
+                    _ => "still loading",
+                };
+                write!(f, "vk hashes of {circuits:?} are not loaded: {reason}")
+            }
+            Self::Mismatch(circuits) => write!(f, "vk mismatch of {circuits:?}"),
         }
     }
 }
+
+impl std::error::Error for VkHashesError {}
+
+// hashes per chain. The keys are generated once by `load_vk_hashes` as it takes a while.
+static VK_HASHES: Lazy<Mutex<HashMap<u64, VkHashes>>> = Lazy::new(Default::default);
+
+/// return the hex digests of the vks of the circuits of `CIRCUIT_REGISTRY` with the params of
+/// the chain, and of the agg vk if the chain has one, as far as `load_vk_hashes` got. The mock
+/// server has no keys.
+pub fn vk_hashes(chain: &ChainConfig) -> VkHashes {
+    if cfg!(feature = "mock-server") {
+        return VkHashes::default();
+    }
+    VK_HASHES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&chain.chain_id)
+        .cloned()
+        .unwrap_or(VkHashes {
+            status: VkHashesStatus::Loading,
+            ..Default::default()
+        })
+}
+
+/// generate the vks of the chain for `vk_hashes`. Errors and panics of the keygen fail the
+/// chain, the hashes loaded before are kept.
+pub fn load_vk_hashes(chain: &ChainConfig) -> Result<(), String> {
+    if cfg!(feature = "mock-server") {
+        return Ok(());
+    }
+    let result = panic::catch_unwind(AssertUnwindSafe(|| compute_vk_hashes(chain)))
+        .unwrap_or_else(|e| {
+            let msg = e
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| e.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Err(anyhow!("keygen panicked: {msg}"))
+        })
+        .map_err(|e| format!("{e:?}"));
+    update_vk_hashes(chain.chain_id, |loaded| match &result {
+        Ok(()) => {
+            log::info!("vk hashes of chain {}: {:?}", chain.chain_id, loaded.hashes);
+            loaded.status = VkHashesStatus::Loaded;
+        }
+        Err(e) => {
+            loaded.status = VkHashesStatus::Failed;
+            loaded.error = Some(e.clone());
+        }
+    });
+    result
+}
+
+fn update_vk_hashes(chain_id: u64, f: impl FnOnce(&mut VkHashes)) {
+    let mut cache = VK_HASHES.lock().unwrap_or_else(PoisonError::into_inner);
+    f(cache.entry(chain_id).or_insert_with(|| VkHashes {
+        status: VkHashesStatus::Loading,
+        ..Default::default()
+    }));
+}
+
+// the hash of each vk is added once generated, without holding the lock during keygen.
+fn compute_vk_hashes(chain: &ChainConfig) -> anyhow::Result<()> {
+    let params = load_kzg_params(&chain.params_dir, *DEGREE)?;
+    for circuit in CIRCUIT_REGISTRY.entries() {
+        let hash = hash_bytes(&serialize_vk(&circuit.keygen_vk(&params)?));
+        update_vk_hashes(chain.chain_id, |loaded| {
+            loaded.hashes.insert(circuit.name.clone(), hash);
+        });
+    }
+    if let Some(file) = &chain.agg_vk_file {
+        let hash = hash_bytes(&fs::read(file)?);
+        update_vk_hashes(chain.chain_id, |loaded| {
+            loaded.hashes.insert("agg".to_string(), hash);
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "mock-server"))]
+#[test]
+fn test_spec_while_loading() {
+    let chain: ChainConfig = serde_json::from_value(serde_json::json!({ "chain_id": 0 })).unwrap();
+    update_vk_hashes(chain.chain_id, |loaded| {
+        loaded.hashes.insert("super".to_string(), "00".to_string());
+    });
+
+    let loaded = vk_hashes(&chain);
+    assert_eq!(loaded.status, VkHashesStatus::Loading);
+    let spec = ZkSpec::new(&chain, loaded);
+    assert_eq!(spec.vk_hashes_status, VkHashesStatus::Loading);
+    // the spec is there without expectations, and the hashes loaded so far are checked
+    assert_eq!(spec.check_vk_hashes(&BTreeMap::new()), Ok(()));
+    let expected = |name: &str, hash: &str| BTreeMap::from([(name.to_string(), hash.to_string())]);
+    assert_eq!(spec.check_vk_hashes(&expected("super", "00")), Ok(()));
+    assert_eq!(
+        spec.check_vk_hashes(&expected("super", "01")),
+        Err(VkHashesError::Mismatch(vec!["super".to_string()]))
+    );
+    assert_eq!(
+        spec.check_vk_hashes(&expected("agg", "00")),
+        Err(VkHashesError::NotLoaded {
+            circuits: vec!["agg".to_string()],
+            status: VkHashesStatus::Loading,
+        })
+    );
+
+    // once loaded, a hash which is not there is a mismatch
+    update_vk_hashes(chain.chain_id, |loaded| {
+        loaded.status = VkHashesStatus::Loaded
+    });
+    let spec = ZkSpec::new(&chain, vk_hashes(&chain));
+    assert_eq!(
+        spec.check_vk_hashes(&expected("agg", "00")),
+        Err(VkHashesError::Mismatch(vec!["agg".to_string()]))
+    );
+}
@@ -1,5 +1,9 @@
+use flate2::read::GzDecoder;
 use log::{error, info};
 use std::fmt::Display;
+use std::io::{Cursor, Read};
+use types::compact;
+use types::eth::{block_trace_from_reader_skipping_memory, BlockTrace};
 
 pub static KROMA_MSG_HEADER: &str = "KROMA";
 
@@ -14,3 +18,22 @@ pub fn kroma_info<S: AsRef<str> + Display>(msg: S) {
 pub fn kroma_err<S: AsRef<str> + Display>(msg: S) {
     error!("{}", kroma_msg(msg))
 }
+
+/// decode a trace uploaded as base64 encoded gzip JSON or compact binary trace.
+/// The upload is base64 decoded, decompressed and parsed as a stream, so that only the upload
+/// itself is held in memory. Compact traces are small and decoded from a buffer.
+pub fn decode_compressed_trace(trace: &str) -> anyhow::Result<BlockTrace> {
+    let mut encoded = trace.as_bytes();
+    let mut decoder = base64::read::DecoderReader::new(&mut encoded, base64::STANDARD);
+    let mut head = Vec::with_capacity(compact::MAGIC.len());
+    (&mut decoder)
+        .take(compact::MAGIC.len() as u64)
+        .read_to_end(&mut head)?;
+    if compact::is_compact(&head) {
+        decoder.read_to_end(&mut head)?;
+        return Ok(compact::decode_block_trace(&head)?);
+    }
+    Ok(block_trace_from_reader_skipping_memory(GzDecoder::new(
+        Cursor::new(head).chain(decoder),
+    ))?)
+}
@@ -0,0 +1,633 @@
+//! Compact binary encoding of traces.
+//!
+//! An encoded trace is `MAGIC | version (u16 le) | kind (u8) | deflate(bincode(payload))`.
+//! Stack, memory and storage words as well as byte blobs (codes and trie nodes) are stored once
+//! in tables and referred to by index, since the same values repeat over and over in a trace.
+
+use crate::eth::{
+    AccountProofWrapper, BlockTrace, ExecStep, ExecutionResult, ExtraData, StorageProofWrapper,
+    StorageTrace,
+};
+use eth_types::evm_types::OpcodeId;
+use eth_types::Word;
+use ethers_core::types::{Address, Bytes, H256, U256};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::io::{self, Read, Write};
+
+pub const MAGIC: &[u8; 4] = b"KTRC";
+pub const VERSION: u16 = 2;
+/// oldest version still decoded. Version 1 block traces have no history hashes.
+pub const MIN_VERSION: u16 = 1;
+/// file extension of compact traces.
+pub const EXTENSION: &str = "ktrace";
+
+const HEADER_LEN: usize = MAGIC.len() + 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Kind {
+    BlockTrace = 0,
+    ExecutionResult = 1,
+    StorageTrace = 2,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Bincode(bincode::Error),
+    Json(serde_json::Error),
+    BadMagic,
+    UnsupportedVersion(u16),
+    UnexpectedKind(u8),
+    BadIndex(u32),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "io error: {e}"),
+            Error::Bincode(e) => write!(f, "bincode error: {e}"),
+            Error::Json(e) => write!(f, "json error: {e}"),
+            Error::BadMagic => write!(f, "not a compact trace"),
+            Error::UnsupportedVersion(v) => write!(f, "unsupported compact trace version {v}"),
+            Error::UnexpectedKind(k) => write!(f, "unexpected compact trace kind {k}"),
+            Error::BadIndex(i) => write!(f, "table index {i} out of range"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<bincode::Error> for Error {
+    fn from(e: bincode::Error) -> Self {
+        Error::Bincode(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+/// return true if the buffer starts like a compact trace.
+pub fn is_compact(buf: &[u8]) -> bool {
+    buf.starts_with(MAGIC)
+}
+
+pub fn encode_block_trace(trace: &BlockTrace) -> Result<Vec<u8>, Error> {
+    let mut tables = Interners::default();
+    let value = CompactBlockTrace::encode(trace, &mut tables)?;
+    encode(Kind::BlockTrace, tables, value)
+}
+
+pub fn decode_block_trace(buf: &[u8]) -> Result<BlockTrace, Error> {
+    if version(buf)? == 1 {
+        let (tables, value) = decode::<CompactBlockTraceV1>(Kind::BlockTrace, buf)?;
+        return value.decode(&tables);
+    }
+    let (tables, value) = decode::<CompactBlockTrace>(Kind::BlockTrace, buf)?;
+    value.decode(&tables)
+}
+
+pub fn encode_execution_result(result: &ExecutionResult) -> Result<Vec<u8>, Error> {
+    let mut tables = Interners::default();
+    let value = CompactExecutionResult::encode(result, &mut tables);
+    encode(Kind::ExecutionResult, tables, value)
+}
+
+pub fn decode_execution_result(buf: &[u8]) -> Result<ExecutionResult, Error> {
+    let (tables, value) = decode::<CompactExecutionResult>(Kind::ExecutionResult, buf)?;
+    value.decode(&tables)
+}
+
+pub fn encode_storage_trace(trace: &StorageTrace) -> Result<Vec<u8>, Error> {
+    let mut tables = Interners::default();
+    let value = CompactStorageTrace::encode(trace, &mut tables);
+    encode(Kind::StorageTrace, tables, value)
+}
+
+pub fn decode_storage_trace(buf: &[u8]) -> Result<StorageTrace, Error> {
+    let (tables, value) = decode::<CompactStorageTrace>(Kind::StorageTrace, buf)?;
+    value.decode(&tables)
+}
+
+fn encode<T: Serialize>(kind: Kind, tables: Interners, value: T) -> Result<Vec<u8>, Error> {
+    let mut buf = Vec::with_capacity(HEADER_LEN);
+    buf.extend_from_slice(MAGIC);
+    buf.extend_from_slice(&VERSION.to_le_bytes());
+    buf.push(kind as u8);
+
+    let container = Container {
+        words: tables.words.items,
+        blobs: tables.blobs.items,
+        value,
+    };
+    let mut encoder = DeflateEncoder::new(buf, Compression::default());
+    bincode::serialize_into(&mut encoder, &container)?;
+    Ok(encoder.finish()?)
+}
+
+/// return the version of the compact trace, if it is supported.
+pub fn version(buf: &[u8]) -> Result<u16, Error> {
+    if buf.len() < HEADER_LEN || !is_compact(buf) {
+        return Err(Error::BadMagic);
+    }
+    let version = u16::from_le_bytes([buf[4], buf[5]]);
+    if !(MIN_VERSION..=VERSION).contains(&version) {
+        return Err(Error::UnsupportedVersion(version));
+    }
+    Ok(version)
+}
+
+fn decode<T: DeserializeOwned>(kind: Kind, buf: &[u8]) -> Result<(Tables, T), Error> {
+    version(buf)?;
+    if buf[6] != kind as u8 {
+        return Err(Error::UnexpectedKind(buf[6]));
+    }
+
+    let mut decoder = DeflateDecoder::new(&buf[HEADER_LEN..]);
+    let container: Container<T> = bincode::deserialize_from(&mut decoder)?;
+    // make sure the whole payload was consumed
+    if decoder.read(&mut [0u8; 1])? != 0 {
+        return Err(Error::Io(io::ErrorKind::InvalidData.into()));
+    }
+    let tables = Tables {
+        words: container.words,
+        blobs: container.blobs,
+    };
+    Ok((tables, container.value))
+}
+
+#[derive(Serialize, Deserialize)]
+struct Container<T> {
+    words: Vec<[u8; 32]>,
+    blobs: Vec<Vec<u8>>,
+    value: T,
+}
+
+struct Interner<T> {
+    index: HashMap<T, u32>,
+    items: Vec<T>,
+}
+
+impl<T> Default for Interner<T> {
+    fn default() -> Self {
+        Self {
+            index: HashMap::new(),
+            items: Vec::new(),
+        }
+    }
+}
+
+impl<T: Hash + Eq + Clone> Interner<T> {
+    fn intern(&mut self, item: T) -> u32 {
+        if let Some(idx) = self.index.get(&item) {
+            return *idx;
+        }
+        let idx = self.items.len() as u32;
+        self.index.insert(item.clone(), idx);
+        self.items.push(item);
+        idx
+    }
+}
+
+#[derive(Default)]
+struct Interners {
+    words: Interner<[u8; 32]>,
+    blobs: Interner<Vec<u8>>,
+}
+
+impl Interners {
+    fn word(&mut self, w: &Word) -> u32 {
+        self.words.intern(word_to_bytes(w))
+    }
+
+    fn blob(&mut self, b: &Bytes) -> u32 {
+        self.blobs.intern(b.to_vec())
+    }
+
+    fn blobs(&mut self, bs: &[Bytes]) -> Vec<u32> {
+        bs.iter().map(|b| self.blob(b)).collect()
+    }
+}
+
+struct Tables {
+    words: Vec<[u8; 32]>,
+    blobs: Vec<Vec<u8>>,
+}
+
+impl Tables {
+    fn word(&self, idx: u32) -> Result<Word, Error> {
+        self.words
+            .get(idx as usize)
+            .map(|w| U256::from_big_endian(w))
+            .ok_or(Error::BadIndex(idx))
+    }
+
+    fn blob(&self, idx: u32) -> Result<Bytes, Error> {
+        self.blobs
+            .get(idx as usize)
+            .map(|b| Bytes::from(b.clone()))
+            .ok_or(Error::BadIndex(idx))
+    }
+
+    fn blobs(&self, idxs: &[u32]) -> Result<Vec<Bytes>, Error> {
+        idxs.iter().map(|idx| self.blob(*idx)).collect()
+    }
+}
+
+fn word_to_bytes(w: &Word) -> [u8; 32] {
+    let mut buf = [0u8; 32];
+    w.to_big_endian(&mut buf);
+    buf
+}
+
+#[derive(Serialize, Deserialize)]
+struct CompactBlockTrace {
+    chain_id: [u8; 32],
+    coinbase: CompactAccount,
+    // the header and transactions are small and carry flattened or optional fields,
+    // so they are kept as JSON.
+    header: Vec<u8>,
+    transactions: Vec<u8>,
+    execution_results: Vec<CompactExecutionResult>,
+    storage_trace: CompactStorageTrace,
+    history_hashes: Vec<[u8; 32]>,
+}
+
+impl CompactBlockTrace {
+    fn encode(trace: &BlockTrace, tables: &mut Interners) -> Result<Self, Error> {
+        Ok(Self {
+            chain_id: word_to_bytes(&trace.chain_id),
+            coinbase: CompactAccount::encode(&trace.coinbase, tables),
+            header: serde_json::to_vec(&trace.header)?,
+            transactions: serde_json::to_vec(&trace.transactions)?,
+            execution_results: trace
+                .execution_results
+                .iter()
+                .map(|r| CompactExecutionResult::encode(r, tables))
+                .collect(),
+            storage_trace: CompactStorageTrace::encode(&trace.storage_trace, tables),
+            history_hashes: trace.history_hashes.iter().map(|h| h.0).collect(),
+        })
+    }
+
+    fn decode(self, tables: &Tables) -> Result<BlockTrace, Error> {
+        Ok(BlockTrace {
+            chain_id: U256::from_big_endian(&self.chain_id),
+            coinbase: self.coinbase.decode(tables)?,
+            header: serde_json::from_slice(&self.header)?,
+            transactions: serde_json::from_slice(&self.transactions)?,
+            execution_results: self
+                .execution_results
+                .into_iter()
+                .map(|r| r.decode(tables))
+                .collect::<Result<_, _>>()?,
+            storage_trace: self.storage_trace.decode(tables)?,
+            history_hashes: self.history_hashes.into_iter().map(H256).collect(),
+        })
+    }
+}
+
+/// `CompactBlockTrace` of version 1.
+#[derive(Serialize, Deserialize)]
+struct CompactBlockTraceV1 {
+    chain_id: [u8; 32],
+    coinbase: CompactAccount,
+    header: Vec<u8>,
+    transactions: Vec<u8>,
+    execution_results: Vec<CompactExecutionResult>,
+    storage_trace: CompactStorageTrace,
+}
+
+impl CompactBlockTraceV1 {
+    fn decode(self, tables: &Tables) -> Result<BlockTrace, Error> {
+        CompactBlockTrace {
+            chain_id: self.chain_id,
+            coinbase: self.coinbase,
+            header: self.header,
+            transactions: self.transactions,
+            execution_results: self.execution_results,
+            storage_trace: self.storage_trace,
+            history_hashes: vec![],
+        }
+        .decode(tables)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CompactExecutionResult {
+    gas: u64,
+    failed: bool,
+    return_value: String,
+    from: Option<CompactAccount>,
+    to: Option<CompactAccount>,
+    account_after: Vec<CompactAccount>,
+    account_created: Option<CompactAccount>,
+    code_hash: Option<[u8; 32]>,
+    byte_code: Option<String>,
+    exec_steps: Vec<CompactExecStep>,
+}
+
+impl CompactExecutionResult {
+    fn encode(r: &ExecutionResult, tables: &mut Interners) -> Self {
+        let mut account = |a: &AccountProofWrapper| CompactAccount::encode(a, tables);
+        let from = r.from.as_ref().map(&mut account);
+        let to = r.to.as_ref().map(&mut account);
+        let account_after = r.account_after.iter().map(&mut account).collect();
+        let account_created = r.account_created.as_ref().map(&mut account);
+        Self {
+            gas: r.gas,
+            failed: r.failed,
+            return_value: r.return_value.clone(),
+            from,
+            to,
+            account_after,
+            account_created,
+            code_hash: r.code_hash.map(|h| h.0),
+            byte_code: r.byte_code.clone(),
+            exec_steps: r
+                .exec_steps
+                .iter()
+                .map(|s| CompactExecStep::encode(s, tables))
+                .collect(),
+        }
+    }
+
+    fn decode(self, tables: &Tables) -> Result<ExecutionResult, Error> {
+        let account = |a: CompactAccount| a.decode(tables);
+        Ok(ExecutionResult {
+            gas: self.gas,
+            failed: self.failed,
+            return_value: self.return_value,
+            from: self.from.map(account).transpose()?,
+            to: self.to.map(account).transpose()?,
+            account_after: self
+                .account_after
+                .into_iter()
+                .map(account)
+                .collect::<Result<_, _>>()?,
+            account_created: self.account_created.map(account).transpose()?,
+            code_hash: self.code_hash.map(H256),
+            byte_code: self.byte_code,
+            exec_steps: self
+                .exec_steps
+                .into_iter()
+                .map(|s| s.decode(tables))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CompactExecStep {
+    pc: u64,
+    op: u8,
+    gas: u64,
+    gas_cost: u64,
+    refund: u64,
+    depth: i64,
+    error: Option<String>,
+    stack: Option<Vec<u32>>,
+    memory: Option<Vec<u32>>,
+    storage: Option<Vec<(u32, u32)>>,
+    extra_data: Option<CompactExtraData>,
+}
+
+impl CompactExecStep {
+    fn encode(s: &ExecStep, tables: &mut Interners) -> Self {
+        let mut words = |ws: &Vec<Word>| ws.iter().map(|w| tables.word(w)).collect::<Vec<_>>();
+        let stack = s.stack.as_ref().map(&mut words);
+        let memory = s.memory.as_ref().map(&mut words);
+        let storage = s.storage.as_ref().map(|storage| {
+            let mut slots: Vec<_> = storage.iter().collect();
+            slots.sort();
+            slots
+                .into_iter()
+                .map(|(k, v)| (tables.word(k), tables.word(v)))
+                .collect()
+        });
+        Self {
+            pc: s.pc,
+            op: s.op.as_u8(),
+            gas: s.gas,
+            gas_cost: s.gas_cost,
+            refund: s.refund,
+            depth: s.depth as i64,
+            error: s.error.clone(),
+            stack,
+            memory,
+            storage,
+            extra_data: s
+                .extra_data
+                .as_ref()
+                .map(|d| CompactExtraData::encode(d, tables)),
+        }
+    }
+
+    fn decode(self, tables: &Tables) -> Result<ExecStep, Error> {
+        let words = |idxs: Vec<u32>| {
+            idxs.into_iter()
+                .map(|idx| tables.word(idx))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let storage = self
+            .storage
+            .map(|slots| {
+                slots
+                    .into_iter()
+                    .map(|(k, v)| Ok((tables.word(k)?, tables.word(v)?)))
+                    .collect::<Result<HashMap<_, _>, Error>>()
+            })
+            .transpose()?;
+        Ok(ExecStep {
+            pc: self.pc,
+            op: OpcodeId::from(self.op),
+            gas: self.gas,
+            gas_cost: self.gas_cost,
+            refund: self.refund,
+            depth: self.depth as isize,
+            error: self.error,
+            stack: self.stack.map(words).transpose()?,
+            memory: self.memory.map(words).transpose()?,
+            storage,
+            extra_data: self.extra_data.map(|d| d.decode(tables)).transpose()?,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CompactExtraData {
+    code_list: Option<Vec<u32>>,
+    proof_list: Option<Vec<CompactAccount>>,
+}
+
+impl CompactExtraData {
+    fn encode(d: &ExtraData, tables: &mut Interners) -> Self {
+        Self {
+            code_list: d.code_list.as_ref().map(|codes| tables.blobs(codes)),
+            proof_list: d.proof_list.as_ref().map(|proofs| {
+                proofs
+                    .iter()
+                    .map(|p| CompactAccount::encode(p, tables))
+                    .collect()
+            }),
+        }
+    }
+
+    fn decode(self, tables: &Tables) -> Result<ExtraData, Error> {
+        Ok(ExtraData {
+            code_list: self.code_list.map(|c| tables.blobs(&c)).transpose()?,
+            proof_list: self
+                .proof_list
+                .map(|proofs| {
+                    proofs
+                        .into_iter()
+                        .map(|p| p.decode(tables))
+                        .collect::<Result<_, _>>()
+                })
+                .transpose()?,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CompactAccount {
+    address: Option<[u8; 20]>,
+    nonce: Option<u64>,
+    balance: Option<[u8; 32]>,
+    code_hash: Option<[u8; 32]>,
+    proof: Option<Vec<u32>>,
+    storage: Option<CompactStorage>,
+}
+
+impl CompactAccount {
+    fn encode(a: &AccountProofWrapper, tables: &mut Interners) -> Self {
+        Self {
+            address: a.address.map(|a| a.0),
+            nonce: a.nonce,
+            balance: a.balance.as_ref().map(word_to_bytes),
+            code_hash: a.code_hash.map(|h| h.0),
+            proof: a.proof.as_ref().map(|p| tables.blobs(p)),
+            storage: a.storage.as_ref().map(|s| CompactStorage {
+                key: s.key.as_ref().map(word_to_bytes),
+                value: s.value.as_ref().map(word_to_bytes),
+                proof: s.proof.as_ref().map(|p| tables.blobs(p)),
+            }),
+        }
+    }
+
+    fn decode(self, tables: &Tables) -> Result<AccountProofWrapper, Error> {
+        let storage = match self.storage {
+            Some(s) => Some(StorageProofWrapper {
+                key: s.key.map(|k| U256::from_big_endian(&k)),
+                value: s.value.map(|v| U256::from_big_endian(&v)),
+                proof: s.proof.map(|p| tables.blobs(&p)).transpose()?,
+            }),
+            None => None,
+        };
+        Ok(AccountProofWrapper {
+            address: self.address.map(Address::from),
+            nonce: self.nonce,
+            balance: self.balance.map(|b| U256::from_big_endian(&b)),
+            code_hash: self.code_hash.map(H256),
+            proof: self.proof.map(|p| tables.blobs(&p)).transpose()?,
+            storage,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CompactStorage {
+    key: Option<[u8; 32]>,
+    value: Option<[u8; 32]>,
+    proof: Option<Vec<u32>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CompactStorageTrace {
+    root_before: [u8; 32],
+    root_after: [u8; 32],
+    proofs: Option<Vec<([u8; 20], Vec<u32>)>>,
+    storage_proofs: Vec<([u8; 20], Vec<([u8; 32], Vec<u32>)>)>,
+    deletion_proofs: Vec<u32>,
+}
+
+impl CompactStorageTrace {
+    fn encode(t: &StorageTrace, tables: &mut Interners) -> Self {
+        // sort map entries, so that the same trace always gets the same encoding
+        let proofs = t.proofs.as_ref().map(|proofs| {
+            let mut proofs: Vec<_> = proofs.iter().collect();
+            proofs.sort_by_key(|(addr, _)| **addr);
+            proofs
+                .into_iter()
+                .map(|(addr, proof)| (addr.0, tables.blobs(proof)))
+                .collect()
+        });
+        let mut storage_proofs: Vec<_> = t.storage_proofs.iter().collect();
+        storage_proofs.sort_by_key(|(addr, _)| **addr);
+        let storage_proofs = storage_proofs
+            .into_iter()
+            .map(|(addr, slots)| {
+                let mut slots: Vec<_> = slots.iter().collect();
+                slots.sort_by_key(|(key, _)| **key);
+                let slots = slots
+                    .into_iter()
+                    .map(|(key, proof)| (word_to_bytes(key), tables.blobs(proof)))
+                    .collect();
+                (addr.0, slots)
+            })
+            .collect();
+        Self {
+            root_before: t.root_before.0,
+            root_after: t.root_after.0,
+            proofs,
+            storage_proofs,
+            deletion_proofs: tables.blobs(&t.deletion_proofs),
+        }
+    }
+
+    fn decode(self, tables: &Tables) -> Result<StorageTrace, Error> {
+        let proofs = self
+            .proofs
+            .map(|proofs| {
+                proofs
+                    .into_iter()
+                    .map(|(addr, proof)| Ok((Address::from(addr), tables.blobs(&proof)?)))
+                    .collect::<Result<HashMap<_, _>, Error>>()
+            })
+            .transpose()?;
+        let storage_proofs = self
+            .storage_proofs
+            .into_iter()
+            .map(|(addr, slots)| {
+                let slots = slots
+                    .into_iter()
+                    .map(|(key, proof)| Ok((U256::from_big_endian(&key), tables.blobs(&proof)?)))
+                    .collect::<Result<HashMap<_, _>, Error>>()?;
+                Ok((Address::from(addr), slots))
+            })
+            .collect::<Result<HashMap<_, _>, Error>>()?;
+        Ok(StorageTrace {
+            root_before: H256(self.root_before),
+            root_after: H256(self.root_after),
+            proofs,
+            storage_proofs,
+            deletion_proofs: tables.blobs(&self.deletion_proofs)?,
+        })
+    }
+}
@@ -1,9 +1,17 @@
 use eth_types::evm_types::{Gas, GasCost, OpcodeId, ProgramCounter, Stack, Storage};
 use eth_types::geth_types::DEPOSIT_TX_TYPE;
-use eth_types::{AccessList, Block, GethExecStep, GethExecTrace, Hash, Transaction, Word, H256};
+use eth_types::{
+    AccessList, Block, GethExecStep, GethExecTrace, Hash, ToAddress, Transaction, Word, H256,
+};
 use ethers_core::types::{Address, Bytes, U256, U64};
-use serde::{Deserialize, Deserializer, Serialize};
-use std::collections::HashMap;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufReader, Read};
+
+/// number of previous blocks `BLOCKHASH` can return the hash of.
+pub const MAX_HISTORY_HASHES: usize = 256;
 
 fn u64_to_word<'de, D>(deserializer: D) -> Result<U256, D::Error>
 where
@@ -13,6 +21,82 @@ where
     Ok((i).into())
 }
 
+thread_local! {
+    // set while `block_trace_from_reader_skipping_memory` parses
+    static SKIP_MEMORY: Cell<bool> = Cell::new(false);
+}
+
+fn memory<'de, D>(deserializer: D) -> Result<Option<Vec<Word>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    if SKIP_MEMORY.with(Cell::get) {
+        IgnoredAny::deserialize(deserializer)?;
+        return Ok(None);
+    }
+    Deserialize::deserialize(deserializer)
+}
+
+// serialize maps in key order, so that serialized traces are canonical, e.g. for hashing.
+struct SortedMap<'a, K, V>(&'a HashMap<K, V>);
+
+impl<K: Ord + Serialize, V: Serialize> Serialize for SortedMap<'_, K, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().collect::<BTreeMap<_, _>>())
+    }
+}
+
+fn sorted_map<S, K, V>(map: &Option<HashMap<K, V>>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    K: Ord + Serialize,
+    V: Serialize,
+{
+    map.as_ref().map(SortedMap).serialize(serializer)
+}
+
+fn sorted_nested_map<S, K, K2, V>(
+    map: &HashMap<K, HashMap<K2, V>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    K: Ord + Serialize,
+    K2: Ord + Serialize,
+    V: Serialize,
+{
+    serializer.collect_map(
+        map.iter()
+            .map(|(k, v)| (k, SortedMap(v)))
+            .collect::<BTreeMap<_, _>>(),
+    )
+}
+
+/// parse a `BlockTrace` from the reader without loading the whole input into memory.
+pub fn block_trace_from_reader<R: Read>(reader: R) -> serde_json::Result<BlockTrace> {
+    serde_json::from_reader(BufReader::new(reader))
+}
+
+/// same as `block_trace_from_reader`, but drops the memory of the exec steps while parsing, which
+/// `From<&ExecStep> for GethExecStep` discards anyway. For traces parsed only to be proved.
+pub fn block_trace_from_reader_skipping_memory<R: Read>(
+    reader: R,
+) -> serde_json::Result<BlockTrace> {
+    struct Restore(bool);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            SKIP_MEMORY.with(|skip| skip.set(self.0));
+        }
+    }
+    let _restore = Restore(SKIP_MEMORY.with(|skip| skip.replace(true)));
+    block_trace_from_reader(reader)
+}
+
+/// same as `block_trace_from_reader`, for a trace wrapped in a JSON-RPC response.
+pub fn block_trace_from_json_rpc_reader<R: Read>(reader: R) -> serde_json::Result<BlockTrace> {
+    serde_json::from_reader::<_, BlockTraceJsonRpcResult>(BufReader::new(reader)).map(|r| r.result)
+}
+
 #[derive(Deserialize, Serialize, Default, Debug, Clone)]
 pub struct BlockTrace {
     #[serde(rename = "chainID", default, deserialize_with = "u64_to_word")]
@@ -24,28 +108,56 @@ pub struct BlockTrace {
     pub execution_results: Vec<ExecutionResult>,
     #[serde(rename = "storageTrace")]
     pub storage_trace: StorageTrace,
+    /// hashes of the up to `MAX_HISTORY_HASHES` blocks before, oldest first, so the last one
+    /// is the parent hash. Needed to prove `BLOCKHASH`.
+    #[serde(
+        rename = "historyHashes",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub history_hashes: Vec<H256>,
     //    #[serde(rename = "mptwitness", default)]
     //    pub mpt_witness: Vec<SMTTrace>,
 }
 
+impl BlockTrace {
+    /// return what `BLOCKHASH` returns for the block: its hash if it is one of the
+    /// `MAX_HISTORY_HASHES` blocks before, zero otherwise. `None` if the trace does not carry
+    /// the hash or has no block number.
+    pub fn block_hash(&self, number: U256) -> Option<H256> {
+        let current = U256::from(self.header.number?.as_u64());
+        if number >= current || current - number > U256::from(MAX_HISTORY_HASHES) {
+            return Some(H256::zero());
+        }
+        let distance = (current - number).as_usize();
+        self.history_hashes
+            .len()
+            .checked_sub(distance)
+            .map(|idx| self.history_hashes[idx])
+    }
+}
+
 #[derive(Deserialize, Serialize, Default, Debug, Clone)]
 pub struct BlockTraceJsonRpcResult {
     pub result: BlockTrace,
 }
 
-impl From<BlockTrace> for EthBlock {
-    fn from(mut b: BlockTrace) -> Self {
+/// Fails on deposit txs missing their deposit fields.
+impl TryFrom<BlockTrace> for EthBlock {
+    type Error = DepositTxError;
+
+    fn try_from(b: BlockTrace) -> Result<Self, Self::Error> {
         let mut txs = Vec::new();
-        for (idx, tx_data) in b.transactions.iter_mut().enumerate() {
+        for (idx, tx_data) in b.transactions.iter().enumerate() {
             let tx_idx = Some(U64::from(idx));
-            let tx = tx_data.to_eth_tx(b.header.hash, b.header.number, tx_idx);
+            let tx = tx_data.to_eth_tx(b.header.hash, b.header.number, tx_idx)?;
             txs.push(tx)
         }
-        EthBlock {
+        Ok(EthBlock {
             transactions: txs,
             difficulty: 0.into(),
             ..b.header
-        }
+        })
     }
 }
 
@@ -76,18 +188,43 @@ pub struct TransactionTrace {
     pub mint: Option<U256>,
     #[serde(rename = "sourceHash")]
     pub source_hash: Option<H256>,
+    #[serde(
+        rename = "isSystemTx",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub is_system_tx: Option<bool>,
     #[serde(rename = "accessList")]
     pub access_list: Option<AccessList>,
+
+    // EIP-1559 tx, `gas_price` is the effective gas price
+    #[serde(
+        rename = "maxFeePerGas",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_fee_per_gas: Option<U256>,
+    #[serde(
+        rename = "maxPriorityFeePerGas",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_priority_fee_per_gas: Option<U256>,
 }
 
 impl TransactionTrace {
+    /// fails on a deposit tx missing its deposit fields, see `KromaDepositTx`.
     pub fn to_eth_tx(
         &self,
         block_hash: Option<H256>,
         block_number: Option<U64>,
         transaction_index: Option<U64>,
-    ) -> Transaction {
-        let mut tx = Transaction {
+    ) -> Result<Transaction, DepositTxError> {
+        if self.type_ as u64 == DEPOSIT_TX_TYPE {
+            let deposit = KromaDepositTx::try_from(self)?;
+            return Ok(deposit.to_eth_tx(self.nonce, block_hash, block_number, transaction_index));
+        }
+        Ok(Transaction {
             hash: self.tx_hash,
             nonce: U256::from(self.nonce),
             block_hash,
@@ -104,23 +241,275 @@ impl TransactionTrace {
             s: self.s,
             transaction_type: Some(U64::from(self.type_)),
             access_list: self.access_list.clone(),
-            max_priority_fee_per_gas: None,
-            max_fee_per_gas: None,
+            max_priority_fee_per_gas: self.max_priority_fee_per_gas,
+            max_fee_per_gas: self.max_fee_per_gas,
             chain_id: Some(self.chain_id),
             other: Default::default(),
+        })
+    }
+
+    /// Return the gas price the tx pays with the base fee of its block, the `gas_price` of the
+    /// trace checked against the fee caps of an EIP-1559 tx. Deposit txs pay nothing.
+    pub fn effective_gas_price(&self, base_fee: Option<U256>) -> Result<U256, FeeError> {
+        match self.type_ as u64 {
+            DEPOSIT_TX_TYPE => return Ok(U256::zero()),
+            // legacy and EIP-2930 txs pay their gas price
+            0 | 1 => return Ok(self.gas_price),
+            _ => {}
+        }
+        let base_fee = base_fee.ok_or(FeeError::MissingBaseFee(self.tx_hash))?;
+        let gas_price = match (self.max_fee_per_gas, self.max_priority_fee_per_gas) {
+            (Some(max_fee), Some(max_priority_fee)) => {
+                if max_fee < base_fee {
+                    return Err(FeeError::FeeCapBelowBaseFee {
+                        tx_hash: self.tx_hash,
+                        max_fee,
+                        base_fee,
+                    });
+                }
+                if max_priority_fee > max_fee {
+                    return Err(FeeError::TipAboveFeeCap {
+                        tx_hash: self.tx_hash,
+                        max_priority_fee,
+                        max_fee,
+                    });
+                }
+                let gas_price = max_fee.min(base_fee + max_priority_fee);
+                if gas_price != self.gas_price {
+                    return Err(FeeError::GasPriceMismatch {
+                        tx_hash: self.tx_hash,
+                        trace: self.gas_price,
+                        computed: gas_price,
+                    });
+                }
+                gas_price
+            }
+            // traces without the fee caps carry the effective gas price only
+            _ => self.gas_price,
         };
-        if let Some(tx_type) = tx.transaction_type {
-            if tx_type == U64::from(DEPOSIT_TX_TYPE) {
-                let mint = self.mint.unwrap();
-                let source_hash = self.source_hash.unwrap();
-
-                let mint_json_string = format!("\"mint\": \"{mint:#?}\"");
-                let source_hash_json_string = format!("\"sourceHash\": \"{source_hash:#?}\"");
-                let json_value = format!("{{{mint_json_string}, {source_hash_json_string}}}");
-                tx.other = serde_json::from_str(json_value.as_str()).unwrap();
+        // the tip to the coinbase is what is paid above the base fee
+        if gas_price < base_fee {
+            return Err(FeeError::GasPriceBelowBaseFee {
+                tx_hash: self.tx_hash,
+                gas_price,
+                base_fee,
+            });
+        }
+        Ok(gas_price)
+    }
+}
+
+/// Fees of a tx which can not be paid in its block, see `effective_gas_price`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FeeError {
+    MissingBaseFee(H256),
+    FeeCapBelowBaseFee {
+        tx_hash: H256,
+        max_fee: U256,
+        base_fee: U256,
+    },
+    TipAboveFeeCap {
+        tx_hash: H256,
+        max_priority_fee: U256,
+        max_fee: U256,
+    },
+    GasPriceMismatch {
+        tx_hash: H256,
+        trace: U256,
+        computed: U256,
+    },
+    GasPriceBelowBaseFee {
+        tx_hash: H256,
+        gas_price: U256,
+        base_fee: U256,
+    },
+}
+
+impl std::fmt::Display for FeeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingBaseFee(tx_hash) => {
+                write!(f, "tx {tx_hash:?} is in a block without base fee")
+            }
+            Self::FeeCapBelowBaseFee {
+                tx_hash,
+                max_fee,
+                base_fee,
+            } => write!(
+                f,
+                "tx {tx_hash:?} has max fee {max_fee} below the base fee {base_fee}"
+            ),
+            Self::TipAboveFeeCap {
+                tx_hash,
+                max_priority_fee,
+                max_fee,
+            } => write!(
+                f,
+                "tx {tx_hash:?} has max priority fee {max_priority_fee} above the max fee {max_fee}"
+            ),
+            Self::GasPriceMismatch {
+                tx_hash,
+                trace,
+                computed,
+            } => write!(
+                f,
+                "tx {tx_hash:?} has gas price {trace}, but pays {computed} with its fee caps"
+            ),
+            Self::GasPriceBelowBaseFee {
+                tx_hash,
+                gas_price,
+                base_fee,
+            } => write!(
+                f,
+                "tx {tx_hash:?} has gas price {gas_price} below the base fee {base_fee}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FeeError {}
+
+/// A Kroma deposit tx, of type `DEPOSIT_TX_TYPE`. Deposit txs are not signed and carry nonce 0
+/// in traces, they execute with the nonce `from` has in the state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KromaDepositTx {
+    pub tx_hash: H256,
+    pub source_hash: H256,
+    pub from: Address,
+    pub to: Option<Address>,
+    /// minted to `from` on L2 before the tx executes.
+    pub mint: U256,
+    pub value: U256,
+    pub gas: u64,
+    pub is_system_tx: bool,
+    pub data: Bytes,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DepositTxError {
+    NotDeposit {
+        tx_hash: H256,
+        type_: u8,
+    },
+    MissingMint(H256),
+    MissingSourceHash(H256),
+    /// the nonce of `from` in the state differs from the one before the tx in its trace.
+    NonceMismatch {
+        tx_hash: H256,
+        state_nonce: u64,
+        trace_nonce: u64,
+    },
+}
+
+impl std::fmt::Display for DepositTxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotDeposit { tx_hash, type_ } => {
+                write!(f, "tx {tx_hash:?} of type {type_} is not a deposit tx")
+            }
+            Self::MissingMint(tx_hash) => write!(f, "deposit tx {tx_hash:?} has no mint"),
+            Self::MissingSourceHash(tx_hash) => {
+                write!(f, "deposit tx {tx_hash:?} has no source hash")
             }
+            Self::NonceMismatch {
+                tx_hash,
+                state_nonce,
+                trace_nonce,
+            } => write!(
+                f,
+                "deposit tx {tx_hash:?} executes with nonce {trace_nonce}, but it is {state_nonce} in the state"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DepositTxError {}
+
+impl TryFrom<&TransactionTrace> for KromaDepositTx {
+    type Error = DepositTxError;
+
+    fn try_from(tx: &TransactionTrace) -> Result<Self, Self::Error> {
+        if tx.type_ as u64 != DEPOSIT_TX_TYPE {
+            return Err(DepositTxError::NotDeposit {
+                tx_hash: tx.tx_hash,
+                type_: tx.type_,
+            });
+        }
+        Ok(Self {
+            tx_hash: tx.tx_hash,
+            source_hash: tx
+                .source_hash
+                .ok_or(DepositTxError::MissingSourceHash(tx.tx_hash))?,
+            from: tx.from,
+            to: tx.to,
+            mint: tx.mint.ok_or(DepositTxError::MissingMint(tx.tx_hash))?,
+            value: tx.value,
+            gas: tx.gas,
+            is_system_tx: tx.is_system_tx.unwrap_or_default(),
+            data: tx.data.clone(),
+        })
+    }
+}
+
+impl KromaDepositTx {
+    /// return the nonce the tx executes with, `state_nonce` of `from`, checked against the
+    /// nonce of `from` before the tx in its execution result if it is there.
+    pub fn check_nonce(
+        &self,
+        state_nonce: u64,
+        execution_result: &ExecutionResult,
+    ) -> Result<u64, DepositTxError> {
+        let trace_nonce = execution_result
+            .from
+            .as_ref()
+            .filter(|from| from.address.map_or(true, |address| address == self.from))
+            .and_then(|from| from.nonce);
+        match trace_nonce {
+            Some(trace_nonce) if trace_nonce != state_nonce => Err(DepositTxError::NonceMismatch {
+                tx_hash: self.tx_hash,
+                state_nonce,
+                trace_nonce,
+            }),
+            _ => Ok(state_nonce),
+        }
+    }
+
+    /// convert into the tx bus-mapping executes, with the nonce of `from`.
+    pub fn to_eth_tx(
+        &self,
+        nonce: u64,
+        block_hash: Option<H256>,
+        block_number: Option<U64>,
+        transaction_index: Option<U64>,
+    ) -> Transaction {
+        // bus-mapping reads the deposit fields from the extra fields of the tx
+        let mint = self.mint;
+        let source_hash = self.source_hash;
+        let mint_json_string = format!("\"mint\": \"{mint:#?}\"");
+        let source_hash_json_string = format!("\"sourceHash\": \"{source_hash:#?}\"");
+        let json_value = format!("{{{mint_json_string}, {source_hash_json_string}}}");
+        Transaction {
+            hash: self.tx_hash,
+            nonce: U256::from(nonce),
+            block_hash,
+            block_number,
+            transaction_index,
+            from: self.from,
+            to: self.to,
+            value: self.value,
+            gas_price: Some(U256::zero()),
+            gas: U256::from(self.gas),
+            input: self.data.clone(),
+            v: U64::zero(),
+            r: U256::zero(),
+            s: U256::zero(),
+            transaction_type: Some(U64::from(DEPOSIT_TX_TYPE)),
+            access_list: None,
+            max_priority_fee_per_gas: None,
+            max_fee_per_gas: None,
+            chain_id: Some(U256::zero()),
+            other: serde_json::from_str(json_value.as_str()).unwrap(),
         }
-        tx
     }
 }
 
@@ -133,8 +522,13 @@ pub struct StorageTrace {
     pub root_before: Hash,
     #[serde(rename = "rootAfter")]
     pub root_after: Hash,
+    #[serde(serialize_with = "sorted_map")]
     pub proofs: Option<AccountTrieProofs>,
-    #[serde(rename = "storageProofs", default)]
+    #[serde(
+        rename = "storageProofs",
+        default,
+        serialize_with = "sorted_nested_map"
+    )]
     pub storage_proofs: StorageTrieProofs,
     #[serde(rename = "deletionProofs", default)]
     pub deletion_proofs: Vec<Bytes>,
@@ -190,7 +584,14 @@ pub struct ExecStep {
     pub depth: isize,
     pub error: Option<String>,
     pub stack: Option<Vec<Word>>,
+    // kept unless parsed by `block_trace_from_reader_skipping_memory`
+    #[serde(
+        default,
+        deserialize_with = "memory",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub memory: Option<Vec<Word>>,
+    #[serde(serialize_with = "sorted_map")]
     pub storage: Option<HashMap<Word, Word>>,
     #[serde(rename = "extraData")]
     pub extra_data: Option<ExtraData>,
@@ -253,3 +654,261 @@ pub struct StorageProofWrapper {
     pub value: Option<U256>,
     pub proof: Option<Vec<Bytes>>,
 }
+
+/// What is wrong with a trace, see `validate`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum TraceIssueKind {
+    MissingBlockNumber,
+    ExecutionResultCount,
+    MissingDepositField,
+    InvalidFee,
+    InvalidHistoryHashes,
+    MissingHistoryHash,
+    MissingAccountProof,
+    MissingStorageProof,
+    MissingExtraData,
+    MissingCode,
+    MissingStack,
+}
+
+/// A structural problem found in a trace, located by tx and step index where it applies.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TraceIssue {
+    pub kind: TraceIssueKind,
+    pub tx_index: Option<usize>,
+    pub step_index: Option<usize>,
+    pub message: String,
+}
+
+impl TraceIssue {
+    fn new(kind: TraceIssueKind, message: String) -> Self {
+        Self {
+            kind,
+            tx_index: None,
+            step_index: None,
+            message,
+        }
+    }
+
+    fn at(mut self, tx_index: usize, step_index: Option<usize>) -> Self {
+        self.tx_index = Some(tx_index);
+        self.step_index = step_index;
+        self
+    }
+}
+
+impl std::fmt::Display for TraceIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(tx_index) = self.tx_index {
+            write!(f, "tx[{tx_index}]")?;
+        }
+        if let Some(step_index) = self.step_index {
+            write!(f, ".step[{step_index}]")?;
+        }
+        if self.tx_index.is_some() {
+            write!(f, ": ")?;
+        }
+        write!(f, "{:?}: {}", self.kind, self.message)
+    }
+}
+
+/// check the structural invariants the witness generation relies on, so that a malformed trace
+/// is reported up front instead of panicking deep inside bus-mapping.
+/// An empty result means the trace is fine.
+pub fn validate(block_trace: &BlockTrace) -> Vec<TraceIssue> {
+    let mut issues = Vec::new();
+
+    if block_trace.header.number.is_none() {
+        issues.push(TraceIssue::new(
+            TraceIssueKind::MissingBlockNumber,
+            "header has no block number".to_string(),
+        ));
+    }
+    if block_trace.execution_results.len() != block_trace.transactions.len() {
+        issues.push(TraceIssue::new(
+            TraceIssueKind::ExecutionResultCount,
+            format!(
+                "{} execution results for {} transactions",
+                block_trace.execution_results.len(),
+                block_trace.transactions.len()
+            ),
+        ));
+    }
+
+    if block_trace.history_hashes.len() > MAX_HISTORY_HASHES {
+        issues.push(TraceIssue::new(
+            TraceIssueKind::InvalidHistoryHashes,
+            format!(
+                "{} history hashes, at most {} expected",
+                block_trace.history_hashes.len(),
+                MAX_HISTORY_HASHES
+            ),
+        ));
+    }
+    if let Some(parent_hash) = block_trace.history_hashes.last() {
+        if *parent_hash != block_trace.header.parent_hash {
+            issues.push(TraceIssue::new(
+                TraceIssueKind::InvalidHistoryHashes,
+                format!(
+                    "last history hash {:?} is not the parent hash {:?}",
+                    parent_hash, block_trace.header.parent_hash
+                ),
+            ));
+        }
+    }
+
+    let storage_trace = &block_trace.storage_trace;
+    for (tx_index, tx) in block_trace.transactions.iter().enumerate() {
+        if let Err(e) = tx.effective_gas_price(block_trace.header.base_fee_per_gas) {
+            issues.push(
+                TraceIssue::new(TraceIssueKind::InvalidFee, e.to_string()).at(tx_index, None),
+            );
+        }
+        if tx.type_ as u64 == DEPOSIT_TX_TYPE {
+            if tx.mint.is_none() {
+                issues.push(
+                    TraceIssue::new(
+                        TraceIssueKind::MissingDepositField,
+                        "deposit tx has no mint".to_string(),
+                    )
+                    .at(tx_index, None),
+                );
+            }
+            if tx.source_hash.is_none() {
+                issues.push(
+                    TraceIssue::new(
+                        TraceIssueKind::MissingDepositField,
+                        "deposit tx has no sourceHash".to_string(),
+                    )
+                    .at(tx_index, None),
+                );
+            }
+        }
+
+        for address in std::iter::once(tx.from).chain(tx.to) {
+            let has_proof = storage_trace
+                .proofs
+                .as_ref()
+                .map_or(false, |proofs| proofs.contains_key(&address));
+            if !has_proof {
+                issues.push(
+                    TraceIssue::new(
+                        TraceIssueKind::MissingAccountProof,
+                        format!("no account proof of {address:?}"),
+                    )
+                    .at(tx_index, None),
+                );
+            }
+        }
+
+        if let Some(execution_result) = block_trace.execution_results.get(tx_index) {
+            validate_steps(tx, execution_result, block_trace, tx_index, &mut issues);
+        }
+    }
+
+    issues
+}
+
+fn validate_steps(
+    tx: &TransactionTrace,
+    execution_result: &ExecutionResult,
+    block_trace: &BlockTrace,
+    tx_index: usize,
+    issues: &mut Vec<TraceIssue>,
+) {
+    let storage_trace = &block_trace.storage_trace;
+    // the storage owner of each call depth, `None` where it can not be told from the trace,
+    // e.g. inside a contract creation.
+    let mut contexts = vec![tx.to];
+    let mut callee = None;
+    for (step_index, step) in execution_result.exec_steps.iter().enumerate() {
+        let mut issue = |kind, message| {
+            issues.push(TraceIssue::new(kind, message).at(tx_index, Some(step_index)))
+        };
+
+        let depth = step.depth.max(1) as usize;
+        contexts.truncate(depth);
+        while contexts.len() < depth {
+            contexts.push(callee.take());
+        }
+        let context = contexts.last().copied().flatten();
+
+        let stack = step.stack.as_deref().unwrap_or_default();
+        let needed_stack = match step.op {
+            OpcodeId::CALL | OpcodeId::CALLCODE => 7,
+            OpcodeId::DELEGATECALL | OpcodeId::STATICCALL => 6,
+            OpcodeId::EXTCODESIZE | OpcodeId::SLOAD | OpcodeId::BLOCKHASH => 1,
+            OpcodeId::SSTORE => 2,
+            OpcodeId::EXTCODECOPY => 4,
+            _ => 0,
+        };
+        if stack.len() < needed_stack {
+            issue(
+                TraceIssueKind::MissingStack,
+                format!("{:?} needs {} stack items", step.op, needed_stack),
+            );
+            continue;
+        }
+
+        callee = match step.op {
+            OpcodeId::CALL | OpcodeId::STATICCALL => Some(stack[stack.len() - 2].to_address()),
+            OpcodeId::CALLCODE | OpcodeId::DELEGATECALL => context,
+            _ => None,
+        };
+
+        match step.op {
+            OpcodeId::CALL
+            | OpcodeId::CALLCODE
+            | OpcodeId::DELEGATECALL
+            | OpcodeId::STATICCALL
+            | OpcodeId::EXTCODESIZE
+            | OpcodeId::EXTCODECOPY => {
+                // same code index as `build_codedb` looks up
+                let code_index = match step.op {
+                    OpcodeId::EXTCODESIZE | OpcodeId::EXTCODECOPY => 0,
+                    _ if tx.to.is_none() => 0,
+                    _ => 1,
+                };
+                match step.extra_data.as_ref().map(|data| &data.code_list) {
+                    None => issue(
+                        TraceIssueKind::MissingExtraData,
+                        format!("{:?} has no extraData", step.op),
+                    ),
+                    Some(code_list) => {
+                        if code_list.as_ref().map_or(0, Vec::len) <= code_index {
+                            issue(
+                                TraceIssueKind::MissingCode,
+                                format!("{:?} has no code at {}", step.op, code_index),
+                            );
+                        }
+                    }
+                }
+            }
+            OpcodeId::SLOAD | OpcodeId::SSTORE => {
+                let key = stack[stack.len() - 1];
+                if let Some(address) = context {
+                    let has_proof = storage_trace
+                        .storage_proofs
+                        .get(&address)
+                        .map_or(false, |slots| slots.contains_key(&key));
+                    if !has_proof {
+                        issue(
+                            TraceIssueKind::MissingStorageProof,
+                            format!("no storage proof of {address:?} slot {key:#x}"),
+                        );
+                    }
+                }
+            }
+            OpcodeId::BLOCKHASH => {
+                let number = stack[stack.len() - 1];
+                if block_trace.block_hash(number).is_none() {
+                    issue(
+                        TraceIssueKind::MissingHistoryHash,
+                        format!("no history hash of block {number}"),
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+}
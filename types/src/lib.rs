@@ -1,3 +1,4 @@
+pub mod compact;
 pub mod eth;
 
 pub mod base64 {
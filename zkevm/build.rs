@@ -0,0 +1,13 @@
+// Expose the git rev of the zkevm-circuits dependency as `ZKEVM_CIRCUITS_REV`, so that the
+// provers can tell which circuits they run.
+fn main() {
+    println!("cargo:rerun-if-changed=Cargo.toml");
+    let manifest = std::fs::read_to_string("Cargo.toml").expect("failed to read Cargo.toml");
+    let rev = manifest
+        .lines()
+        .find(|line| line.starts_with("zkevm-circuits "))
+        .and_then(|line| line.split("rev = \"").nth(1))
+        .and_then(|rest| rest.split('"').next())
+        .unwrap_or("unknown");
+    println!("cargo:rustc-env=ZKEVM_CIRCUITS_REV={rev}");
+}
@@ -0,0 +1,128 @@
+use std::fs;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use serde::de::DeserializeOwned;
+use serde_derive::{Deserialize, Serialize};
+use types::eth::BlockTrace;
+
+use crate::circuit::{AGG_DEGREE, DEGREE};
+use crate::io::write_file_atomically;
+use crate::utils::{hash_block_traces, hash_bytes};
+
+/// Stages of an agg proof which are checkpointed and skipped on resume. The witness block is not
+/// checkpointed as it is not serializable, it is rebuilt when the target proof is redone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Stage {
+    /// the proof of the target circuit.
+    Target,
+    /// the proof of the aggregation circuit.
+    Agg,
+}
+
+impl Stage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stage::Target => "target",
+            Stage::Agg => "agg",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope<T> {
+    key: String,
+    stage: Stage,
+    digest: String,
+    payload: T,
+}
+
+/// return the checkpoint key of a batch. It changes with the traces and the circuit degrees.
+pub fn checkpoint_key(block_traces: &[BlockTrace]) -> String {
+    let traces_hash = hash_block_traces(block_traces);
+    hash_bytes(format!("{}:{}:{}", traces_hash, *DEGREE, *AGG_DEGREE).as_bytes())
+}
+
+/// A directory of stage checkpoints named `<key>_<stage>.json`.
+#[derive(Debug, Clone)]
+pub struct CheckpointStore {
+    dir: PathBuf,
+}
+
+impl CheckpointStore {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    pub fn path(&self, key: &str, stage: Stage) -> PathBuf {
+        self.dir.join(format!("{}_{}.json", key, stage.as_str()))
+    }
+
+    pub fn save<T: serde::Serialize>(&self, key: &str, stage: Stage, payload: &T) -> Result<()> {
+        let digest = hash_bytes(&serde_json::to_vec(payload)?);
+        let envelope = Envelope {
+            key: key.to_string(),
+            stage,
+            digest,
+            payload,
+        };
+        // a half-written checkpoint must never be picked up, so write aside and rename.
+        let path = self.path(key, stage);
+        write_file_atomically(&path, &serde_json::to_vec(&envelope)?)?;
+        log::info!("checkpoint {} saved to {:?}", stage.as_str(), path);
+        Ok(())
+    }
+
+    /// return the checkpointed payload of the stage if it exists and is intact.
+    /// A corrupted checkpoint is removed.
+    pub fn load<T: serde::Serialize + DeserializeOwned>(
+        &self,
+        key: &str,
+        stage: Stage,
+    ) -> Option<T> {
+        let path = self.path(key, stage);
+        if !path.exists() {
+            return None;
+        }
+        match Self::read(&path, key, stage) {
+            Ok(payload) => {
+                log::info!("checkpoint {} loaded from {:?}", stage.as_str(), path);
+                Some(payload)
+            }
+            Err(e) => {
+                log::warn!("discard checkpoint {:?}: {}", path, e);
+                self.remove(key, stage);
+                None
+            }
+        }
+    }
+
+    pub fn remove(&self, key: &str, stage: Stage) {
+        let _ = fs::remove_file(self.path(key, stage));
+    }
+
+    fn read<T: serde::Serialize + DeserializeOwned>(
+        path: &Path,
+        key: &str,
+        stage: Stage,
+    ) -> Result<T> {
+        let fd = fs::File::open(path)?;
+        let envelope: Envelope<T> = serde_json::from_reader(BufReader::new(fd))?;
+        if envelope.key != key || envelope.stage != stage {
+            bail!(
+                "checkpoint belongs to {} of {}",
+                envelope.stage.as_str(),
+                envelope.key
+            );
+        }
+        let digest = hash_bytes(&serde_json::to_vec(&envelope.payload)?);
+        if digest != envelope.digest {
+            bail!("digest mismatch: {} vs {}", digest, envelope.digest);
+        }
+        Ok(envelope.payload)
+    }
+}
@@ -16,13 +16,22 @@ use zkevm_circuits::util::SubCircuit;
 use zkevm_circuits::witness;
 
 mod builder;
+mod cache;
+mod capacity;
+mod estimate;
+mod registry;
 
 use crate::utils::read_env_var;
 
 pub use self::builder::{
     block_traces_to_witness_block, calculate_row_usage_of_trace,
-    calculate_row_usage_of_witness_block, check_batch_capacity, SUB_CIRCUIT_NAMES,
+    calculate_row_usage_of_witness_block, check_batch_capacity, circuits_params, resolve_chain_id,
+    ChainIdError, SUB_CIRCUIT_NAMES,
 };
+pub use self::cache::{WitnessCache, WITNESS_CACHE};
+pub use self::capacity::{check_capacity, usable_rows, CapacityCheck};
+pub use self::estimate::{estimate_row_usage_of_trace, estimate_row_usage_of_tx};
+pub use self::registry::{CircuitEntry, CircuitRegistry, AGG_PROOF_ID, CIRCUIT_REGISTRY};
 
 ////// params for degree = 19 ////////////
 /*
@@ -47,6 +56,10 @@ const MAX_EXP_STEPS: usize = 100_000;
 pub static CHAIN_ID: Lazy<u64> = Lazy::new(|| read_env_var("CHAIN_ID", 255));
 pub static AGG_DEGREE: Lazy<usize> = Lazy::new(|| read_env_var("AGG_DEGREE", 26));
 pub static AUTO_TRUNCATE: Lazy<bool> = Lazy::new(|| read_env_var("AUTO_TRUNCATE", true));
+/// whether the SuperCircuit proves the state root transition, with the `zktrie` feature.
+pub const STATE_ROOT_PROVING: bool = cfg!(feature = "zktrie");
+/// git rev of the zkevm-circuits the circuits are built from.
+pub const ZKEVM_CIRCUITS_REV: &str = env!("ZKEVM_CIRCUITS_REV");
 
 pub trait TargetCircuit {
     type Inner: Halo2Circuit<Fr>;
@@ -68,7 +81,7 @@ pub trait TargetCircuit {
     where
         Self: Sized,
     {
-        let witness_block = block_traces_to_witness_block(block_traces)?;
+        let witness_block = WITNESS_CACHE.witness_block(block_traces)?;
         Self::from_witness_block(&witness_block)
     }
     fn from_witness_block(
@@ -78,7 +91,7 @@ pub trait TargetCircuit {
         Self: Sized;
 
     fn estimate_rows(block_traces: &[BlockTrace]) -> anyhow::Result<usize> {
-        let witness_block = block_traces_to_witness_block(block_traces)?;
+        let witness_block = WITNESS_CACHE.witness_block(block_traces)?;
         Ok(Self::estimate_rows_from_witness_block(&witness_block))
     }
     fn estimate_rows_from_witness_block(_witness_block: &witness::Block<Fr>) -> usize {
@@ -1,18 +1,19 @@
-use super::{MAX_CALLDATA, MAX_EXP_STEPS, MAX_RWS, MAX_TXS};
+use super::{CHAIN_ID, MAX_CALLDATA, MAX_EXP_STEPS, MAX_RWS, MAX_TXS};
 use crate::circuit::{
-    TargetCircuit, AUTO_TRUNCATE, CHAIN_ID, DEGREE, MAX_INNER_BLOCKS, MAX_KECCAK_ROWS,
+    usable_rows, TargetCircuit, AUTO_TRUNCATE, MAX_INNER_BLOCKS, MAX_KECCAK_ROWS, WITNESS_CACHE,
 };
 use anyhow::bail;
 use bus_mapping::circuit_input_builder::{self, BlockHead, CircuitInputBuilder, CircuitsParams};
 use bus_mapping::state_db::{Account, CodeDB, StateDB};
 use eth_types::{evm_types::OpcodeId, geth_types::DEPOSIT_TX_TYPE, ToAddress};
-use ethers_core::types::{Bytes, U256};
+use ethers_core::types::{Address, Bytes, U256, U64};
 use halo2_proofs::halo2curves::bn256::Fr;
 use is_even::IsEven;
 use itertools::Itertools;
 use mpt_zktrie::state::ZktrieState;
+use std::collections::HashMap;
 use std::time::Instant;
-use types::eth::{BlockTrace, EthBlock, ExecStep};
+use types::eth::{BlockTrace, EthBlock, ExecStep, KromaDepositTx};
 use zkevm_circuits::{
     evm_circuit::witness::{block_apply_mpt_state, block_convert, Block},
     util::SubCircuit,
@@ -27,10 +28,9 @@ pub const SUB_CIRCUIT_NAMES: [&str; 11] = [
     "evm", "state", "bytecode", "copy", "keccak", "tx", "rlp", "exp", "pi", "poseidon", "mpt",
 ];
 
-// TODO: optimize it later
+/// exact row usage of the trace, see `estimate_row_usage_of_trace` for a fast upper bound.
 pub fn calculate_row_usage_of_trace(block_trace: &BlockTrace) -> Result<Vec<usize>, anyhow::Error> {
-    let witness_block = block_traces_to_witness_block(std::slice::from_ref(block_trace))?;
-    calculate_row_usage_of_witness_block(&witness_block)
+    WITNESS_CACHE.row_usage(std::slice::from_ref(block_trace))
 }
 pub fn calculate_row_usage_of_witness_block(
     witness_block: &Block<Fr>,
@@ -55,8 +55,12 @@ pub fn calculate_row_usage_of_witness_block(
     Ok(rows)
 }
 
-/// ...
-pub fn check_batch_capacity(block_traces: &mut Vec<BlockTrace>) -> Result<(), anyhow::Error> {
+/// Truncate the batch to the blocks whose rows fit into `max_rows`, at most `usable_rows()`, per
+/// sub circuit.
+pub fn check_batch_capacity(
+    block_traces: &mut Vec<BlockTrace>,
+    max_rows: usize,
+) -> Result<(), anyhow::Error> {
     let block_traces_len = block_traces.len();
     let total_tx_count = block_traces
         .iter()
@@ -82,6 +86,7 @@ pub fn check_batch_capacity(block_traces: &mut Vec<BlockTrace>) -> Result<(), an
         return Ok(());
     }
 
+    let max_rows = max_rows.min(usable_rows());
     let t = Instant::now();
     let mut acc = Vec::new();
     let mut truncate_idx = block_traces.len();
@@ -106,7 +111,7 @@ pub fn check_batch_capacity(block_traces: &mut Vec<BlockTrace>) -> Result<(), an
             rows,
             rows_and_names
         );
-        if *rows >= (1 << *DEGREE) - 256 {
+        if *rows >= max_rows {
             log::warn!("truncate blocks [{}..{})", idx, block_traces_len);
             truncate_idx = idx;
             break;
@@ -125,6 +130,113 @@ pub fn check_batch_capacity(block_traces: &mut Vec<BlockTrace>) -> Result<(), an
     Ok(())
 }
 
+pub fn circuits_params() -> CircuitsParams {
+    CircuitsParams {
+        max_evm_rows: MAX_RWS,
+        max_rws: MAX_RWS,
+        max_copy_rows: MAX_RWS,
+        max_txs: MAX_TXS,
+        max_calldata: MAX_CALLDATA,
+        max_bytecode: MAX_CALLDATA,
+        max_inner_blocks: MAX_INNER_BLOCKS,
+        max_keccak_rows: MAX_KECCAK_ROWS,
+        max_exp_steps: MAX_EXP_STEPS,
+    }
+}
+
+/// Missing or inconsistent chain IDs of traces, see `resolve_chain_id`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainIdError {
+    /// no block nor non-deposit tx of a non-empty batch tells the chain.
+    Missing,
+    /// a non-deposit tx is signed for another chain than its block.
+    TxMismatch {
+        block_number: Option<u64>,
+        tx_index: usize,
+        expected: U256,
+        found: U256,
+    },
+    /// the blocks of the batch are of different chains.
+    BlockMismatch {
+        block_number: Option<u64>,
+        expected: U256,
+        found: U256,
+    },
+}
+
+impl std::fmt::Display for ChainIdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing => write!(f, "no chain id in the traces"),
+            Self::TxMismatch {
+                block_number,
+                tx_index,
+                expected,
+                found,
+            } => write!(
+                f,
+                "tx {tx_index} of block {block_number:?} has chain id {found}, expected {expected}"
+            ),
+            Self::BlockMismatch {
+                block_number,
+                expected,
+                found,
+            } => write!(
+                f,
+                "block {block_number:?} has chain id {found}, expected {expected}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ChainIdError {}
+
+/// Return the chain ID of the traces. `BlockTrace.chain_id` is the source of truth, the
+/// non-deposit txs have to agree with it, and all blocks have to be of the same chain. Deposit
+/// txs carry no chain ID. Traces without `chainID` are of the chain of their non-deposit txs.
+/// Only the empty batch, which keygen builds the circuits of, is of `CHAIN_ID`.
+pub fn resolve_chain_id(block_traces: &[BlockTrace]) -> Result<U256, ChainIdError> {
+    if block_traces.is_empty() {
+        return Ok(U256::from(*CHAIN_ID));
+    }
+    let mut batch_chain_id: Option<U256> = None;
+    for block_trace in block_traces {
+        let block_number = block_trace.header.number.map(|n| n.as_u64());
+        let mut block_chain_id = Some(block_trace.chain_id).filter(|id| !id.is_zero());
+        for (tx_index, tx) in block_trace.transactions.iter().enumerate() {
+            // pre EIP-155 txs are valid on any chain
+            if tx.type_ as u64 == DEPOSIT_TX_TYPE || tx.chain_id.is_zero() {
+                continue;
+            }
+            match block_chain_id {
+                Some(expected) if expected != tx.chain_id => {
+                    return Err(ChainIdError::TxMismatch {
+                        block_number,
+                        tx_index,
+                        expected,
+                        found: tx.chain_id,
+                    })
+                }
+                Some(_) => {}
+                None => block_chain_id = Some(tx.chain_id),
+            }
+        }
+
+        match (batch_chain_id, block_chain_id) {
+            (Some(expected), Some(found)) if expected != found => {
+                return Err(ChainIdError::BlockMismatch {
+                    block_number,
+                    expected,
+                    found,
+                })
+            }
+            (None, Some(found)) => batch_chain_id = Some(found),
+            _ => {}
+        }
+    }
+    batch_chain_id.ok_or(ChainIdError::Missing)
+}
+
 pub fn block_traces_to_witness_block(
     block_traces: &[BlockTrace],
 ) -> Result<Block<Fr>, anyhow::Error> {
@@ -162,23 +274,7 @@ pub fn block_traces_to_witness_block(
         }),
     )?;
 
-    // TODO(dongchangYoo): It can be simplified after updating that deposit_tx has non-zero chain_id.
-    let chain_ids = block_traces
-        .iter()
-        .flat_map(|block_trace| {
-            block_trace
-                .transactions
-                .iter()
-                .find(|tx_trace| tx_trace.type_ as u64 != DEPOSIT_TX_TYPE)
-                .map(|tx_trace| tx_trace.chain_id)
-        })
-        .collect::<Vec<U256>>();
-
-    let chain_id = if !chain_ids.is_empty() {
-        chain_ids[0]
-    } else {
-        (*CHAIN_ID).into()
-    };
+    let chain_id = resolve_chain_id(block_traces)?;
 
     let mut state_db = zktrie_state.state().clone();
 
@@ -188,44 +284,34 @@ pub fn block_traces_to_witness_block(
     }
 
     let code_db = build_codedb(&state_db, block_traces)?;
-    let circuit_params = CircuitsParams {
-        max_evm_rows: MAX_RWS,
-        max_rws: MAX_RWS,
-        max_copy_rows: MAX_RWS,
-        max_txs: MAX_TXS,
-        max_calldata: MAX_CALLDATA,
-        max_bytecode: MAX_CALLDATA,
-        max_inner_blocks: MAX_INNER_BLOCKS,
-        max_keccak_rows: MAX_KECCAK_ROWS,
-        max_exp_steps: MAX_EXP_STEPS,
-    };
-    let mut builder_block = circuit_input_builder::Block::from_headers(&[], circuit_params);
+    let mut builder_block = circuit_input_builder::Block::from_headers(&[], circuits_params());
     builder_block.chain_id = chain_id;
     builder_block.prev_state_root = U256::from(zktrie_state.root());
     let mut builder = CircuitInputBuilder::new(state_db.clone(), code_db, &builder_block);
     for (idx, block_trace) in block_traces.iter().enumerate() {
         let is_last = idx == block_traces.len() - 1;
-        let mut eth_block: EthBlock = block_trace.clone().into();
-        eth_block.transactions.iter_mut().for_each(|transaction| {
-            if let Some(transaction_type) = transaction.transaction_type {
-                // NOTE(chokobole): The nonce of Kroma deposit tx is set to 0 by default.
-                // This causes an error at assert statement in zkevm-circuits.
-                // See gen_begin_tx_ops in bus-mappings/src/evm/opcodes.rs in zkevm-circuits for details.
-                // So here we explicitly set the known nonce from state db to the transaction.
-                // We have an alternative to make go-ethereum or Kroma-node to set nonce explicitly.
-                // But I think this is the fastest way to satisfy requirements.
-                if transaction_type.as_u64() == DEPOSIT_TX_TYPE {
-                    transaction.nonce = U256::from(builder.sdb.get_nonce(&transaction.from));
-                }
-            }
-        });
+        let mut eth_block = EthBlock::try_from(block_trace.clone())?;
+        set_deposit_tx_nonces(&mut eth_block, block_trace, &builder.sdb)?;
+        // the gas price is what the tx pays, the base fee and the tip to the coinbase are
+        // accounted from it
+        for (tx, tx_trace) in eth_block
+            .transactions
+            .iter_mut()
+            .zip(block_trace.transactions.iter())
+        {
+            tx.gas_price = Some(tx_trace.effective_gas_price(eth_block.base_fee_per_gas)?);
+        }
 
         let mut geth_trace = Vec::new();
         for result in &block_trace.execution_results {
             geth_trace.push(result.into());
         }
-        // TODO: Get the history_hashes.
-        let mut header = BlockHead::new(chain_id, Vec::new(), &eth_block)?;
+        let history_hashes = block_trace
+            .history_hashes
+            .iter()
+            .map(|hash| U256::from_big_endian(hash.as_bytes()))
+            .collect();
+        let mut header = BlockHead::new(chain_id, history_hashes, &eth_block)?;
         // override zeroed minder field with additional "coinbase" field in blocktrace
         if let Some(address) = block_trace.coinbase.address {
             header.coinbase = address;
@@ -270,6 +356,40 @@ pub fn block_traces_to_witness_block(
     Ok(witness_block)
 }
 
+/// Kroma deposit txs carry nonce 0 in traces, but bus-mapping executes them with the nonce of
+/// their sender, see gen_begin_tx_ops in bus-mapping. Set it from the state at the beginning of
+/// the block, counting the txs of the sender before in the block.
+fn set_deposit_tx_nonces(
+    eth_block: &mut EthBlock,
+    block_trace: &BlockTrace,
+    sdb: &StateDB,
+) -> Result<(), anyhow::Error> {
+    let mut nonces: HashMap<Address, u64> = HashMap::new();
+    for (idx, tx_trace) in block_trace.transactions.iter().enumerate() {
+        let nonce = *nonces
+            .entry(tx_trace.from)
+            .or_insert_with(|| sdb.get_nonce(&tx_trace.from));
+        let nonce = if tx_trace.type_ as u64 == DEPOSIT_TX_TYPE {
+            let deposit = KromaDepositTx::try_from(tx_trace)?;
+            let nonce = match block_trace.execution_results.get(idx) {
+                Some(execution_result) => deposit.check_nonce(nonce, execution_result)?,
+                None => nonce,
+            };
+            eth_block.transactions[idx] = deposit.to_eth_tx(
+                nonce,
+                eth_block.hash,
+                eth_block.number,
+                Some(U64::from(idx)),
+            );
+            nonce
+        } else {
+            tx_trace.nonce
+        };
+        nonces.insert(tx_trace.from, nonce + 1);
+    }
+    Ok(())
+}
+
 pub fn decode_bytecode(bytecode: &str) -> Result<Vec<u8>, anyhow::Error> {
     let mut stripped = if let Some(stripped) = bytecode.strip_prefix("0x") {
         stripped.to_string()
@@ -0,0 +1,170 @@
+use super::builder::{
+    block_traces_to_witness_block, calculate_row_usage_of_witness_block, circuits_params,
+    resolve_chain_id,
+};
+use crate::circuit::DEGREE;
+use crate::io::write_file_atomically;
+use crate::utils::{hash_block_traces, hash_bytes, read_env_var};
+use halo2_proofs::halo2curves::bn256::Fr;
+use once_cell::sync::Lazy;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use types::eth::BlockTrace;
+use zkevm_circuits::evm_circuit::witness::Block;
+
+const CACHE_SIZE_VAR: &str = "WITNESS_CACHE_SIZE";
+
+/// Process wide witness cache.
+/// `WITNESS_CACHE_SIZE` bounds the number of witness blocks kept in memory, 0 as default so that
+/// long running processes don't pin witness blocks, see `WitnessCache::opt_in`.
+/// `WITNESS_CACHE_DIR` enables persisting row usages across runs.
+pub static WITNESS_CACHE: Lazy<WitnessCache> = Lazy::new(|| {
+    WitnessCache::new(
+        read_env_var(CACHE_SIZE_VAR, 0),
+        read_env_var("WITNESS_CACHE_DIR", String::new()),
+    )
+});
+
+/// Cache of witness blocks keyed by the hash of the input traces and the circuit params.
+/// Witness blocks are only kept in memory, since they are not serializable. What goes to the
+/// disk dir is the row usage of each witness block, which is what capacity checks need.
+pub struct WitnessCache {
+    capacity: AtomicUsize,
+    dir: Option<PathBuf>,
+    // least recently used first
+    blocks: Mutex<VecDeque<(String, Arc<Block<Fr>>)>>,
+}
+
+impl WitnessCache {
+    /// the disk cache is disabled if `dir` is empty or can not be created.
+    pub fn new(capacity: usize, dir: String) -> Self {
+        let dir = if dir.is_empty() {
+            None
+        } else if let Err(e) = fs::create_dir_all(&dir) {
+            log::error!("failed to create witness cache dir {dir}, disk cache disabled: {e}");
+            None
+        } else {
+            Some(PathBuf::from(dir))
+        };
+        Self {
+            capacity: AtomicUsize::new(capacity),
+            dir,
+            blocks: Default::default(),
+        }
+    }
+
+    /// keep up to `capacity` witness blocks in memory unless `WITNESS_CACHE_SIZE` is set. For the
+    /// bins, which build the witness blocks of the same traces several times.
+    pub fn opt_in(&self, capacity: usize) {
+        if std::env::var(CACHE_SIZE_VAR).is_err() {
+            self.capacity.store(capacity, Ordering::SeqCst);
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity.load(Ordering::SeqCst)
+    }
+
+    /// return the cache key of the traces. Traces of inconsistent chain ids fail to build
+    /// anyway, so their key only has to be distinct.
+    pub fn key(block_traces: &[BlockTrace]) -> String {
+        let traces_hash = hash_block_traces(block_traces);
+        let chain_id = resolve_chain_id(block_traces)
+            .map(|id| id.to_string())
+            .unwrap_or_default();
+        let params = format!("{:?}:{}:{}", circuits_params(), *DEGREE, chain_id);
+        hash_bytes(format!("{traces_hash}:{params}").as_bytes())
+    }
+
+    /// return the witness block of the traces, building it on a miss.
+    pub fn witness_block(&self, block_traces: &[BlockTrace]) -> anyhow::Result<Arc<Block<Fr>>> {
+        if self.capacity() == 0 {
+            return Ok(Arc::new(block_traces_to_witness_block(block_traces)?));
+        }
+        self.witness_block_by_key(&Self::key(block_traces), block_traces)
+    }
+
+    /// return the row usage of the traces per sub circuit, see `SUB_CIRCUIT_NAMES`.
+    pub fn row_usage(&self, block_traces: &[BlockTrace]) -> anyhow::Result<Vec<usize>> {
+        if self.capacity() == 0 && self.dir.is_none() {
+            let witness_block = block_traces_to_witness_block(block_traces)?;
+            return calculate_row_usage_of_witness_block(&witness_block);
+        }
+
+        let key = Self::key(block_traces);
+        if let Some(rows) = self.load_row_usage(&key) {
+            return Ok(rows);
+        }
+        let witness_block = self.witness_block_by_key(&key, block_traces)?;
+        let rows = calculate_row_usage_of_witness_block(&witness_block)?;
+        self.store_row_usage(&key, &rows);
+        Ok(rows)
+    }
+
+    pub fn clear(&self) {
+        self.blocks.lock().unwrap().clear();
+    }
+
+    fn witness_block_by_key(
+        &self,
+        key: &str,
+        block_traces: &[BlockTrace],
+    ) -> anyhow::Result<Arc<Block<Fr>>> {
+        {
+            let mut blocks = self.blocks.lock().unwrap();
+            if let Some(pos) = blocks.iter().position(|(k, _)| k == key) {
+                let entry = blocks.remove(pos).unwrap();
+                let witness_block = entry.1.clone();
+                blocks.push_back(entry);
+                log::debug!("witness cache hit {}", key);
+                return Ok(witness_block);
+            }
+        }
+
+        log::debug!("witness cache miss {}", key);
+        let witness_block = Arc::new(block_traces_to_witness_block(block_traces)?);
+        let capacity = self.capacity();
+        if capacity > 0 {
+            let mut blocks = self.blocks.lock().unwrap();
+            if !blocks.iter().any(|(k, _)| k == key) {
+                while blocks.len() >= capacity {
+                    blocks.pop_front();
+                }
+                blocks.push_back((key.to_string(), witness_block.clone()));
+            }
+        }
+        Ok(witness_block)
+    }
+
+    fn row_usage_path(&self, key: &str) -> Option<PathBuf> {
+        self.dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{key}.rows.json")))
+    }
+
+    fn load_row_usage(&self, key: &str) -> Option<Vec<usize>> {
+        let path = self.row_usage_path(key)?;
+        let buf = fs::read(&path).ok()?;
+        match serde_json::from_slice(&buf) {
+            Ok(rows) => Some(rows),
+            Err(e) => {
+                log::warn!("ignore broken row usage cache {:?}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    fn store_row_usage(&self, key: &str, rows: &[usize]) {
+        if let Some(path) = self.row_usage_path(key) {
+            let result = serde_json::to_vec(rows)
+                .map_err(anyhow::Error::from)
+                .and_then(|buf| Ok(write_file_atomically(&path, &buf)?));
+            if let Err(e) = result {
+                log::warn!("failed to store row usage cache {:?}: {}", path, e);
+            }
+        }
+    }
+}
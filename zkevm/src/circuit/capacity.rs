@@ -0,0 +1,83 @@
+use super::{calculate_row_usage_of_trace, DEGREE, SUB_CIRCUIT_NAMES};
+use anyhow::{bail, Result};
+use serde_derive::{Deserialize, Serialize};
+use types::eth::BlockTrace;
+
+/// Row usage of a block, or of some txs of a block, on top of the usage accumulated so far.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CapacityCheck {
+    pub sub_circuits: Vec<String>,
+    /// rows of the trace alone.
+    pub trace_usage: Vec<usize>,
+    /// rows of the trace added to the accumulated usage.
+    pub usage: Vec<usize>,
+    /// rows left per sub circuit, negative when exceeded.
+    pub headroom: Vec<i64>,
+    /// rows each sub circuit may use.
+    pub usable_rows: usize,
+    /// sub circuit with the least headroom.
+    pub bottleneck: String,
+    /// whether the trace still fits into a proof.
+    pub accepted: bool,
+}
+
+/// rows usable by each sub circuit, the same as `check_batch_capacity` allows.
+pub fn usable_rows() -> usize {
+    (1 << *DEGREE) - 256
+}
+
+/// Check if the trace fits into a proof along with `acc_usage`, the usage of what is already
+/// in it per sub circuit, or nothing if empty. Each sub circuit may use `max_rows`, at most
+/// `usable_rows()`.
+pub fn check_capacity(
+    block_trace: &BlockTrace,
+    acc_usage: &[usize],
+    max_rows: usize,
+) -> Result<CapacityCheck> {
+    if !acc_usage.is_empty() && acc_usage.len() != SUB_CIRCUIT_NAMES.len() {
+        bail!(
+            "expected usage of {} sub circuits, but {}",
+            SUB_CIRCUIT_NAMES.len(),
+            acc_usage.len()
+        );
+    }
+
+    let trace_usage = calculate_row_usage_of_trace(block_trace)?;
+    let usage: Vec<usize> = if acc_usage.is_empty() {
+        trace_usage.clone()
+    } else {
+        trace_usage
+            .iter()
+            .zip(acc_usage.iter())
+            .map(|(rows, acc)| rows + acc)
+            .collect()
+    };
+    let usable_rows = max_rows.min(usable_rows());
+    let headroom: Vec<i64> = usage
+        .iter()
+        .map(|rows| usable_rows as i64 - *rows as i64)
+        .collect();
+    let (idx, min_headroom) = headroom
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, h)| **h)
+        .expect("no sub circuit");
+
+    let check = CapacityCheck {
+        sub_circuits: SUB_CIRCUIT_NAMES.iter().map(|n| n.to_string()).collect(),
+        trace_usage,
+        accepted: *min_headroom > 0,
+        bottleneck: SUB_CIRCUIT_NAMES[idx].to_string(),
+        usage,
+        headroom,
+        usable_rows,
+    };
+    log::debug!(
+        "capacity check of block {:?}: accepted {}, {} headroom {}",
+        block_trace.header.number,
+        check.accepted,
+        check.bottleneck,
+        min_headroom
+    );
+    Ok(check)
+}
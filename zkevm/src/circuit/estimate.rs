@@ -0,0 +1,276 @@
+//! Fast upper bound of the row usage, computed from the steps of the traces without building
+//! the witness. The costs are deliberately pessimistic, the calibration test in
+//! `tests/integration.rs` checks them against the exact row usage of the fixtures.
+
+use super::SUB_CIRCUIT_NAMES;
+use eth_types::evm_types::OpcodeId;
+use ethers_core::types::U256;
+use types::eth::{BlockTrace, ExecStep, ExecutionResult, TransactionTrace};
+
+/// rows of the tallest execution state of the evm circuit, with margin.
+const EVM_ROWS_PER_STEP: usize = 32;
+/// BeginTx and EndTx, padded like the tallest steps.
+const EVM_ROWS_PER_TX: usize = 2 * EVM_ROWS_PER_STEP;
+/// EndBlock and padding.
+const EVM_ROWS_PER_BLOCK: usize = 2 * EVM_ROWS_PER_STEP;
+
+/// stack and call context rws of a plain step.
+const RWS_PER_STEP: usize = 12;
+/// call context switch of a call, create or return.
+const RWS_PER_CALL: usize = 64;
+/// account reads and writes of BeginTx and EndTx, incl. fees.
+const RWS_PER_TX: usize = 64;
+
+/// rows of one keccak-f permutation, 25 rounds of 12 rows.
+const KECCAK_ROWS_PER_CHUNK: usize = 300;
+const KECCAK_RATE: usize = 136;
+/// rlp of a tx besides its calldata and access list.
+const TX_RLP_BYTES: usize = 256;
+const ACCESS_LIST_ITEM_BYTES: usize = 64;
+/// block fields hashed into the public input.
+const PI_BYTES_PER_BLOCK: usize = 512;
+const PI_BYTES_PER_TX: usize = 256;
+
+/// tx table rows per tx, besides calldata.
+const TX_ROWS_PER_TX: usize = 32;
+/// rows of the ecdsa verification of a signature.
+const TX_ROWS_PER_SIGNATURE: usize = 1 << 14;
+const RLP_ROWS_PER_BYTE: usize = 2;
+const EXP_ROWS_PER_BIT: usize = 2 * 7;
+/// poseidon rows per trie node, hashed before and after an update.
+const POSEIDON_ROWS_PER_NODE: usize = 4;
+const POSEIDON_BYTES_PER_ROW: usize = 31;
+/// rows per trie node of the mpt circuit.
+const MPT_ROWS_PER_NODE: usize = 4;
+/// rows of the sub circuits not depending on the traces, e.g. padding.
+const PADDING_ROWS: usize = 256;
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Estimate {
+    evm: usize,
+    state: usize,
+    bytecode: usize,
+    copy: usize,
+    keccak: usize,
+    tx: usize,
+    rlp: usize,
+    exp: usize,
+    pi: usize,
+    poseidon: usize,
+    mpt: usize,
+}
+
+impl Estimate {
+    fn add(&mut self, other: &Self) {
+        self.evm += other.evm;
+        self.state += other.state;
+        self.bytecode += other.bytecode;
+        self.copy += other.copy;
+        self.keccak += other.keccak;
+        self.tx += other.tx;
+        self.rlp += other.rlp;
+        self.exp += other.exp;
+        self.pi += other.pi;
+        self.poseidon += other.poseidon;
+        self.mpt += other.mpt;
+    }
+
+    /// rows per sub circuit, in the order of `SUB_CIRCUIT_NAMES`.
+    fn into_rows(self) -> Vec<usize> {
+        SUB_CIRCUIT_NAMES
+            .iter()
+            .map(|name| match *name {
+                "evm" => self.evm,
+                "state" => self.state,
+                "bytecode" => self.bytecode,
+                "copy" => self.copy,
+                "keccak" => self.keccak,
+                "tx" => self.tx,
+                "rlp" => self.rlp,
+                "exp" => self.exp,
+                "pi" => self.pi,
+                "poseidon" => self.poseidon,
+                "mpt" => self.mpt,
+                name => unreachable!("no estimate of {name}"),
+            })
+            .collect()
+    }
+}
+
+fn keccak_rows(input_len: usize) -> usize {
+    (input_len / KECCAK_RATE + 1) * KECCAK_ROWS_PER_CHUNK
+}
+
+/// return the `n`th stack item from the top, 0 if it is not there. Sizes too large for a
+/// `u32` saturate to `u32::MAX`, far over the capacity of any circuit, so that the estimate of
+/// such a step stays an upper bound.
+fn stack_arg(step: &ExecStep, n: usize) -> usize {
+    step.stack
+        .as_ref()
+        .and_then(|stack| stack.len().checked_sub(n + 1).map(|i| stack[i]))
+        .map_or(0, |v| v.min(U256::from(u32::MAX)).as_usize())
+}
+
+fn step_estimate(step: &ExecStep, estimate: &mut Estimate) {
+    estimate.evm += EVM_ROWS_PER_STEP;
+    estimate.state += RWS_PER_STEP;
+    // a failing step does not copy nor hash anything
+    if step.error.is_some() {
+        estimate.state += RWS_PER_CALL;
+        return;
+    }
+
+    let mut copy_bytes = 0;
+    match step.op {
+        OpcodeId::CALLDATACOPY | OpcodeId::CODECOPY | OpcodeId::RETURNDATACOPY => {
+            copy_bytes = stack_arg(step, 2);
+        }
+        OpcodeId::EXTCODECOPY => copy_bytes = stack_arg(step, 3),
+        OpcodeId::SHA3 => {
+            copy_bytes = stack_arg(step, 1);
+            estimate.keccak += keccak_rows(copy_bytes);
+        }
+        OpcodeId::LOG0 | OpcodeId::LOG1 | OpcodeId::LOG2 | OpcodeId::LOG3 | OpcodeId::LOG4 => {
+            copy_bytes = stack_arg(step, 1)
+        }
+        OpcodeId::RETURN | OpcodeId::REVERT => {
+            copy_bytes = stack_arg(step, 1);
+            estimate.state += RWS_PER_CALL;
+            // returned by a create, the code is deployed and hashed
+            estimate.bytecode += copy_bytes + 1;
+            estimate.keccak += keccak_rows(copy_bytes);
+        }
+        OpcodeId::STOP | OpcodeId::SELFDESTRUCT => estimate.state += RWS_PER_CALL,
+        OpcodeId::CALL | OpcodeId::CALLCODE => {
+            copy_bytes = stack_arg(step, 4) + stack_arg(step, 6);
+            estimate.state += RWS_PER_CALL;
+        }
+        OpcodeId::DELEGATECALL | OpcodeId::STATICCALL => {
+            copy_bytes = stack_arg(step, 3) + stack_arg(step, 5);
+            estimate.state += RWS_PER_CALL;
+        }
+        OpcodeId::CREATE | OpcodeId::CREATE2 => {
+            copy_bytes = stack_arg(step, 2);
+            estimate.state += RWS_PER_CALL;
+            estimate.bytecode += copy_bytes + 1;
+            // address, and init code hash of CREATE2
+            estimate.keccak += keccak_rows(85) + keccak_rows(copy_bytes);
+        }
+        OpcodeId::EXP => {
+            let exponent = step
+                .stack
+                .as_ref()
+                .and_then(|stack| stack.len().checked_sub(2).map(|i| stack[i]))
+                .unwrap_or_default();
+            estimate.exp += (exponent.bits() + 1) * EXP_ROWS_PER_BIT;
+        }
+        _ => {}
+    }
+    // memory rws are counted per byte, for the read and the write
+    estimate.state += 2 * copy_bytes;
+    estimate.copy += 2 * copy_bytes + 2;
+
+    // code of the accounts called or read
+    if let Some(codes) = step.extra_data.as_ref().and_then(|e| e.code_list.as_ref()) {
+        for code in codes {
+            estimate.bytecode += code.len() + 1;
+            estimate.keccak += keccak_rows(code.len());
+            estimate.poseidon += code.len() / POSEIDON_BYTES_PER_ROW + 1;
+        }
+    }
+    if let Some(proofs) = step.extra_data.as_ref().and_then(|e| e.proof_list.as_ref()) {
+        let nodes: usize = proofs
+            .iter()
+            .map(|p| p.proof.as_ref().map_or(0, |p| p.len()))
+            .sum();
+        estimate.poseidon += nodes * POSEIDON_ROWS_PER_NODE;
+        estimate.mpt += nodes * MPT_ROWS_PER_NODE;
+    }
+}
+
+fn tx_estimate(tx: &TransactionTrace, execution_result: &ExecutionResult) -> Estimate {
+    let calldata = tx.data.len();
+    let access_list = tx.access_list.as_ref().map_or(0, |list| {
+        list.0
+            .iter()
+            .map(|item| 1 + item.storage_keys.len())
+            .sum::<usize>()
+    });
+    let rlp_bytes = calldata + TX_RLP_BYTES + access_list * ACCESS_LIST_ITEM_BYTES;
+
+    let mut estimate = Estimate {
+        evm: EVM_ROWS_PER_TX,
+        state: RWS_PER_TX + access_list * 2,
+        // the calldata is copied into the tx table and read through the copy circuit
+        copy: 2 * calldata + 2,
+        // sign data and tx hash
+        keccak: 2 * keccak_rows(rlp_bytes),
+        tx: TX_ROWS_PER_TX + calldata + TX_ROWS_PER_SIGNATURE,
+        rlp: 2 * rlp_bytes * RLP_ROWS_PER_BYTE,
+        pi: PI_BYTES_PER_TX + calldata,
+        ..Default::default()
+    };
+
+    if let Some(code) = &execution_result.byte_code {
+        let len = code.trim_start_matches("0x").len() / 2;
+        estimate.bytecode += len + 1;
+        estimate.keccak += keccak_rows(len);
+        estimate.poseidon += len / POSEIDON_BYTES_PER_ROW + 1;
+    }
+    for step in &execution_result.exec_steps {
+        step_estimate(step, &mut estimate);
+    }
+    estimate
+}
+
+/// Return an upper bound of the rows the tx adds to each sub circuit, see `SUB_CIRCUIT_NAMES`,
+/// computed from its steps. The rows of the block and of the state trie proofs are not
+/// included, see `estimate_row_usage_of_trace`.
+pub fn estimate_row_usage_of_tx(
+    tx: &TransactionTrace,
+    execution_result: &ExecutionResult,
+) -> Vec<usize> {
+    tx_estimate(tx, execution_result).into_rows()
+}
+
+/// Return an upper bound of `calculate_row_usage_of_trace` without building the witness.
+pub fn estimate_row_usage_of_trace(block_trace: &BlockTrace) -> Vec<usize> {
+    let storage_trace = &block_trace.storage_trace;
+    let nodes = storage_trace
+        .proofs
+        .iter()
+        .flat_map(|proofs| proofs.values())
+        .chain(
+            storage_trace
+                .storage_proofs
+                .values()
+                .flat_map(|proofs| proofs.values()),
+        )
+        .map(|proof| proof.len())
+        .sum::<usize>()
+        + storage_trace.deletion_proofs.len();
+
+    let mut estimate = Estimate {
+        evm: EVM_ROWS_PER_BLOCK,
+        state: PADDING_ROWS,
+        bytecode: PADDING_ROWS,
+        copy: PADDING_ROWS,
+        keccak: keccak_rows(PI_BYTES_PER_BLOCK) + 4 * KECCAK_ROWS_PER_CHUNK + PADDING_ROWS,
+        tx: PADDING_ROWS,
+        rlp: PADDING_ROWS,
+        exp: PADDING_ROWS,
+        pi: PI_BYTES_PER_BLOCK + PADDING_ROWS,
+        poseidon: nodes * POSEIDON_ROWS_PER_NODE + PADDING_ROWS,
+        mpt: nodes * MPT_ROWS_PER_NODE + PADDING_ROWS,
+    };
+    for (tx, execution_result) in block_trace
+        .transactions
+        .iter()
+        .zip(block_trace.execution_results.iter())
+    {
+        estimate.add(&tx_estimate(tx, execution_result));
+    }
+    // the public input is hashed as a whole
+    estimate.keccak += keccak_rows(estimate.pi);
+    estimate.into_rows()
+}
@@ -0,0 +1,130 @@
+//! Runtime registry of the `TargetCircuit`s, so that frontends select a circuit by its name or
+//! proof type id instead of matching on the circuit types.
+
+use super::{
+    EvmCircuit, PoseidonCircuit, StateCircuit, SuperCircuit, TargetCircuit, ZktrieCircuit,
+};
+use crate::prover::{Prover, TargetCircuitProof};
+use crate::verifier::Verifier;
+use anyhow::Result;
+use halo2_proofs::dev::VerifyFailure;
+use halo2_proofs::halo2curves::bn256::{Bn256, G1Affine};
+use halo2_proofs::plonk::{keygen_vk, VerifyingKey};
+use halo2_proofs::poly::kzg::commitment::ParamsKZG;
+use once_cell::sync::Lazy;
+use std::fmt;
+use types::eth::BlockTrace;
+
+/// proof type id of the agg proof, which is not a `TargetCircuit`.
+pub const AGG_PROOF_ID: i32 = 4;
+
+/// Every `TargetCircuit`. A new circuit is added here with a new id.
+pub static CIRCUIT_REGISTRY: Lazy<CircuitRegistry> = Lazy::new(|| CircuitRegistry {
+    entries: vec![
+        CircuitEntry::new::<EvmCircuit>(1),
+        CircuitEntry::new::<StateCircuit>(2),
+        CircuitEntry::new::<SuperCircuit>(3),
+        CircuitEntry::new::<ZktrieCircuit>(5),
+        CircuitEntry::new::<PoseidonCircuit>(6),
+    ],
+});
+
+/// A `TargetCircuit` with its functions erased of the type.
+pub struct CircuitEntry {
+    /// proof type id, as requested through the prover server.
+    pub id: i32,
+    pub name: String,
+    prove: fn(&mut Prover, &[BlockTrace]) -> Result<TargetCircuitProof>,
+    verify: fn(&mut Verifier, &TargetCircuitProof) -> Result<()>,
+    mock_prove: fn(&[BlockTrace]) -> Result<()>,
+    mock_prove_failures: fn(&[BlockTrace]) -> Result<Vec<VerifyFailure>>,
+    estimate_rows: fn(&[BlockTrace]) -> Result<usize>,
+    empty: fn(),
+    keygen_vk: fn(&ParamsKZG<Bn256>) -> Result<VerifyingKey<G1Affine>>,
+}
+
+impl fmt::Debug for CircuitEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CircuitEntry")
+            .field("id", &self.id)
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+impl CircuitEntry {
+    fn new<C: TargetCircuit>(id: i32) -> Self {
+        Self {
+            id,
+            name: C::name(),
+            prove: Prover::create_target_circuit_proof_batch::<C>,
+            verify: Verifier::verify_target_circuit_proof::<C>,
+            mock_prove: Prover::mock_prove_target_circuit_batch::<C>,
+            mock_prove_failures: Prover::mock_prove_failures::<C>,
+            estimate_rows: C::estimate_rows,
+            empty: || {
+                C::empty();
+            },
+            keygen_vk: |params| Ok(keygen_vk(params, &C::empty())?),
+        }
+    }
+
+    pub fn prove(
+        &self,
+        prover: &mut Prover,
+        block_traces: &[BlockTrace],
+    ) -> Result<TargetCircuitProof> {
+        (self.prove)(prover, block_traces)
+    }
+
+    pub fn verify(&self, verifier: &mut Verifier, proof: &TargetCircuitProof) -> Result<()> {
+        (self.verify)(verifier, proof)
+    }
+
+    pub fn mock_prove(&self, block_traces: &[BlockTrace]) -> Result<()> {
+        (self.mock_prove)(block_traces)
+    }
+
+    /// see `Prover::mock_prove_failures`.
+    pub fn mock_prove_failures(&self, block_traces: &[BlockTrace]) -> Result<Vec<VerifyFailure>> {
+        (self.mock_prove_failures)(block_traces)
+    }
+
+    pub fn estimate_rows(&self, block_traces: &[BlockTrace]) -> Result<usize> {
+        (self.estimate_rows)(block_traces)
+    }
+
+    /// build the empty circuit keygen uses, see `TargetCircuit::empty`.
+    pub fn empty(&self) {
+        (self.empty)()
+    }
+
+    /// generate the vk of the empty circuit, the same as the prover and verifier use.
+    pub fn keygen_vk(&self, params: &ParamsKZG<Bn256>) -> Result<VerifyingKey<G1Affine>> {
+        (self.keygen_vk)(params)
+    }
+}
+
+#[derive(Debug)]
+pub struct CircuitRegistry {
+    entries: Vec<CircuitEntry>,
+}
+
+impl CircuitRegistry {
+    pub fn get(&self, name: &str) -> Option<&CircuitEntry> {
+        self.entries.iter().find(|e| e.name == name)
+    }
+
+    pub fn by_id(&self, id: i32) -> Option<&CircuitEntry> {
+        self.entries.iter().find(|e| e.id == id)
+    }
+
+    /// the circuits, ordered by id.
+    pub fn entries(&self) -> &[CircuitEntry] {
+        &self.entries
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.entries.iter().map(|e| e.name.as_str()).collect()
+    }
+}
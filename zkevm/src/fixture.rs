@@ -0,0 +1,131 @@
+//! Record traces as test fixtures, laid out as `<chain id>/<block number>.json` and listed in a
+//! manifest.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use types::eth::BlockTrace;
+
+use crate::io::write_file_atomically;
+use crate::utils::hash_block_traces;
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FixtureEntry {
+    /// path relative to the fixture dir.
+    pub path: String,
+    pub chain_id: u64,
+    pub block_number: u64,
+    /// batch the block was fetched in, if any.
+    pub batch: Option<i64>,
+    pub tx_count: usize,
+    pub trace_hash: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct FixtureManifest {
+    pub fixtures: Vec<FixtureEntry>,
+}
+
+impl FixtureManifest {
+    /// load the manifest of the dir, `None` if there is none.
+    pub fn load<P: AsRef<Path>>(dir: P) -> Result<Option<Self>> {
+        let path = dir.as_ref().join(MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let buf = fs::read(&path)?;
+        let manifest =
+            serde_json::from_slice(&buf).with_context(|| format!("invalid manifest {path:?}"))?;
+        Ok(Some(manifest))
+    }
+
+    fn save(&self, dir: &Path) -> Result<()> {
+        Ok(write_file_atomically(
+            &dir.join(MANIFEST_FILE),
+            &serde_json::to_vec_pretty(self)?,
+        )?)
+    }
+
+    /// return the fixture paths, ordered by chain and block.
+    pub fn paths<P: AsRef<Path>>(&self, dir: P) -> Vec<PathBuf> {
+        self.fixtures
+            .iter()
+            .map(|f| dir.as_ref().join(&f.path))
+            .collect()
+    }
+}
+
+/// Writes traces into a fixture dir, pretty-printed and with the `strip` fields removed.
+pub struct FixtureRecorder {
+    dir: PathBuf,
+    strip: Vec<String>,
+}
+
+impl FixtureRecorder {
+    /// `strip` lists JSON field names removed at any depth, e.g. `memory` or `storage`.
+    /// Only optional fields can be stripped, recording fails otherwise.
+    pub fn new<P: AsRef<Path>>(dir: P, strip: Vec<String>) -> Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+            strip,
+        })
+    }
+
+    /// write the trace and add it to the manifest, replacing an earlier record of the block.
+    pub fn record(&self, block_trace: &BlockTrace, batch: Option<i64>) -> Result<PathBuf> {
+        let chain_id = block_trace.chain_id.as_u64();
+        let block_number = block_trace
+            .header
+            .number
+            .context("trace has no block number")?
+            .as_u64();
+
+        let mut value = serde_json::to_value(block_trace)?;
+        for field in &self.strip {
+            strip_field(&mut value, field);
+        }
+        serde_json::from_value::<BlockTrace>(value.clone())
+            .with_context(|| format!("trace can not be loaded without {:?}", self.strip))?;
+
+        let rel_path = format!("{chain_id}/{block_number}.json");
+        let path = self.dir.join(&rel_path);
+        fs::create_dir_all(path.parent().unwrap())?;
+        write_file_atomically(&path, &serde_json::to_vec_pretty(&value)?)?;
+
+        let entry = FixtureEntry {
+            path: rel_path,
+            chain_id,
+            block_number,
+            batch,
+            tx_count: block_trace.transactions.len(),
+            trace_hash: hash_block_traces(std::slice::from_ref(block_trace)),
+        };
+        let mut manifest = FixtureManifest::load(&self.dir)?.unwrap_or_default();
+        manifest.fixtures.retain(|f| f.path != entry.path);
+        manifest.fixtures.push(entry);
+        manifest
+            .fixtures
+            .sort_by_key(|f| (f.chain_id, f.block_number));
+        manifest.save(&self.dir)?;
+
+        log::info!("recorded trace of block {} to {:?}", block_number, path);
+        Ok(path)
+    }
+}
+
+fn strip_field(value: &mut Value, field: &str) {
+    match value {
+        Value::Object(map) => {
+            map.remove(field);
+            map.values_mut().for_each(|v| strip_field(v, field));
+        }
+        Value::Array(values) => values.iter_mut().for_each(|v| strip_field(v, field)),
+        _ => {}
+    }
+}
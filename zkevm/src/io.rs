@@ -1,6 +1,7 @@
 use std::{
+    fs,
     io::{Cursor, Read, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
 use halo2_proofs::{
@@ -75,10 +76,16 @@ pub fn read_file(folder: &mut PathBuf, filename: &str) -> Vec<u8> {
 
 pub fn write_file(folder: &mut PathBuf, filename: &str, buf: &[u8]) {
     folder.push(filename);
-    let mut fd = std::fs::File::create(folder.as_path()).unwrap();
+    write_file_atomically(folder, buf).unwrap();
     folder.pop();
+}
 
-    fd.write_all(buf).unwrap();
+/// write the file aside and rename it, so that a killed process never leaves a half-written file.
+pub fn write_file_atomically(path: &Path, buf: &[u8]) -> std::io::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    fs::write(&tmp_path, buf)?;
+    fs::rename(&tmp_path, path)
 }
 
 pub fn load_target_circuit_params(folder: &mut PathBuf) -> Vec<u8> {
@@ -114,11 +121,9 @@ pub fn load_verify_circuit_proof(folder: &mut PathBuf) -> Vec<u8> {
 }
 
 pub fn write_verify_circuit_params(folder: &mut PathBuf, verify_circuit_params: &ParamsKZG<Bn256>) {
-    folder.push("verify_circuit.params");
-    let mut fd = std::fs::File::create(folder.as_path()).unwrap();
-    folder.pop();
-
-    verify_circuit_params.write(&mut fd).unwrap();
+    let mut buf = Vec::new();
+    verify_circuit_params.write(&mut buf).unwrap();
+    write_file(folder, "verify_circuit.params", &buf)
 }
 
 pub fn serialize_vk(vk: &VerifyingKey<G1Affine>) -> Vec<u8> {
@@ -128,10 +133,7 @@ pub fn serialize_vk(vk: &VerifyingKey<G1Affine>) -> Vec<u8> {
 }
 
 pub fn write_verify_circuit_vk(folder: &mut PathBuf, verify_circuit_vk: &[u8]) {
-    folder.push("verify_circuit.vkey");
-    let mut fd = std::fs::File::create(folder.as_path()).unwrap();
-    folder.pop();
-    fd.write_all(verify_circuit_vk).unwrap()
+    write_file(folder, "verify_circuit.vkey", verify_circuit_vk)
 }
 
 pub fn field_to_bn(f: &Fq) -> BigUint {
@@ -175,10 +177,7 @@ pub fn serialize_verify_circuit_final_pair(pair: &(G1Affine, G1Affine, Vec<Fr>))
 }
 
 pub fn write_verify_circuit_final_pair(folder: &mut PathBuf, buf: &[u8]) {
-    folder.push("verify_circuit_final_pair.data");
-    let mut fd = std::fs::File::create(folder.as_path()).unwrap();
-    folder.pop();
-    fd.write_all(buf).unwrap()
+    write_file(folder, "verify_circuit_final_pair.data", buf)
 }
 
 pub fn write_verify_circuit_instance(folder: &mut PathBuf, buf: &[u8]) {
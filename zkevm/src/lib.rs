@@ -1,5 +1,10 @@
+pub mod checkpoint;
 pub mod circuit;
+pub mod fixture;
 pub mod io;
+pub mod metrics;
+pub mod minimizer;
+pub mod mock_report;
 pub mod prover;
 pub mod utils;
 pub mod verifier;
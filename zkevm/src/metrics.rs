@@ -0,0 +1,250 @@
+//! Metrics of the proving, recorded by the `Prover` for the frontends to export, e.g. through
+//! the `/metrics` of the prover server, and the `ProofProfile` of each proof.
+
+use crate::circuit::{usable_rows, SUB_CIRCUIT_NAMES, ZKEVM_CIRCUITS_REV};
+use once_cell::sync::Lazy;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Process wide metrics of every `Prover`.
+pub static PROVER_METRICS: Lazy<ProverMetrics> = Lazy::new(Default::default);
+
+/// Upper bounds of the row usage buckets, as fractions of the usable rows.
+pub const ROW_USAGE_BUCKETS: [f64; 6] = [0.1, 0.25, 0.5, 0.75, 0.9, 1.0];
+
+/// Stages of a proof, in the order they run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProveStage {
+    /// parsing the uploaded trace.
+    TraceParse,
+    /// checking the traces fit into the circuits, truncating the batch if they don't.
+    CapacityCheck,
+    /// building the witness block of the traces.
+    Witness,
+    /// generating the pk of a circuit, once per circuit and prover.
+    Keygen,
+    /// building the circuit from the witness block.
+    Synthesis,
+    /// creating the proof of a target circuit.
+    CreateProof,
+    /// creating the agg proof of the target proofs.
+    Aggregation,
+}
+
+impl ProveStage {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProveStage::TraceParse => "trace_parse",
+            ProveStage::CapacityCheck => "capacity_check",
+            ProveStage::Witness => "witness",
+            ProveStage::Keygen => "keygen",
+            ProveStage::Synthesis => "synthesis",
+            ProveStage::CreateProof => "create_proof",
+            ProveStage::Aggregation => "aggregation",
+        }
+    }
+}
+
+/// Durations a stage of a circuit took so far.
+#[derive(Debug, Clone, Default)]
+pub struct StageDurations {
+    pub count: u64,
+    pub sum: Duration,
+    pub max: Duration,
+}
+
+/// Histogram of the rows a sub circuit used, with the buckets of `ROW_USAGE_BUCKETS`.
+#[derive(Debug, Clone, Default)]
+pub struct RowUsageHistogram {
+    /// cumulative count per bucket.
+    pub buckets: [u64; ROW_USAGE_BUCKETS.len()],
+    pub count: u64,
+    pub sum: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct ProverMetrics {
+    stages: Mutex<BTreeMap<(ProveStage, String), StageDurations>>,
+    // per sub circuit, see `SUB_CIRCUIT_NAMES`
+    row_usage: Mutex<Vec<RowUsageHistogram>>,
+}
+
+impl ProverMetrics {
+    /// run `f` as the stage of the circuit and record how long it took.
+    pub fn measure<T>(&self, stage: ProveStage, circuit: &str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.record_stage(stage, circuit, start.elapsed());
+        result
+    }
+
+    pub fn record_stage(&self, stage: ProveStage, circuit: &str, elapsed: Duration) {
+        log::debug!("{} of {} took {:?}", stage.name(), circuit, elapsed);
+        let mut stages = self.stages.lock().unwrap();
+        let durations = stages.entry((stage, circuit.to_string())).or_default();
+        durations.count += 1;
+        durations.sum += elapsed;
+        durations.max = durations.max.max(elapsed);
+    }
+
+    /// the durations by stage and circuit.
+    pub fn stages(&self) -> Vec<(ProveStage, String, StageDurations)> {
+        self.stages
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|((stage, circuit), durations)| (*stage, circuit.clone(), durations.clone()))
+            .collect()
+    }
+
+    /// record the row usage of a proved batch per sub circuit.
+    pub fn record_row_usage(&self, rows: &[usize]) {
+        let usable_rows = usable_rows() as f64;
+        let mut row_usage = self.row_usage.lock().unwrap();
+        row_usage.resize(SUB_CIRCUIT_NAMES.len(), Default::default());
+        for (histogram, rows) in row_usage.iter_mut().zip(rows) {
+            for (count, bound) in histogram.buckets.iter_mut().zip(ROW_USAGE_BUCKETS) {
+                if *rows as f64 <= bound * usable_rows {
+                    *count += 1;
+                }
+            }
+            histogram.count += 1;
+            histogram.sum += *rows as u64;
+        }
+    }
+
+    /// the row usage histograms by sub circuit name.
+    pub fn row_usage(&self) -> Vec<(&'static str, RowUsageHistogram)> {
+        let row_usage = self.row_usage.lock().unwrap();
+        SUB_CIRCUIT_NAMES
+            .iter()
+            .zip(row_usage.iter())
+            .map(|(name, histogram)| (*name, histogram.clone()))
+            .collect()
+    }
+}
+
+/// Wall time, CPU time and peak RSS of a stage of a proof.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StageProfile {
+    pub stage: ProveStage,
+    pub circuit: String,
+    pub wall_secs: f64,
+    /// CPU time of the process, which includes the proofs running at the same time.
+    pub cpu_secs: f64,
+    /// peak RSS of the process during the stage. Like `cpu_secs` it is process wide, and the
+    /// stages of the proofs running at the same time reset each other's peak, so it is only
+    /// reliable when one proof runs at a time.
+    pub peak_rss_bytes: u64,
+}
+
+/// Profile of a proof, the stages in the order they ran.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProofProfile {
+    /// version of the prover, to compare profiles across releases.
+    pub version: String,
+    pub circuits_rev: String,
+    pub stages: Vec<StageProfile>,
+}
+
+impl Default for ProofProfile {
+    fn default() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            circuits_rev: ZKEVM_CIRCUITS_REV.to_string(),
+            stages: Vec::new(),
+        }
+    }
+}
+
+impl ProofProfile {
+    /// run `f` as the stage of the circuit, and record it both into the profile and into
+    /// `PROVER_METRICS`.
+    pub fn measure<T>(&mut self, stage: ProveStage, circuit: &str, f: impl FnOnce() -> T) -> T {
+        reset_peak_rss();
+        let cpu_secs = process_cpu_secs();
+        let start = Instant::now();
+        let result = f();
+        let elapsed = start.elapsed();
+        self.stages.push(StageProfile {
+            stage,
+            circuit: circuit.to_string(),
+            wall_secs: elapsed.as_secs_f64(),
+            cpu_secs: process_cpu_secs() - cpu_secs,
+            peak_rss_bytes: process_peak_rss(),
+        });
+        PROVER_METRICS.record_stage(stage, circuit, elapsed);
+        result
+    }
+
+    pub fn wall_secs(&self) -> f64 {
+        self.stages.iter().map(|s| s.wall_secs).sum()
+    }
+
+    pub fn peak_rss_bytes(&self) -> u64 {
+        self.stages
+            .iter()
+            .map(|s| s.peak_rss_bytes)
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+// peak RSS of the process before the last `reset_peak_rss`.
+static PEAK_RSS_BEFORE_RESET: AtomicU64 = AtomicU64::new(0);
+
+/// peak resident set size of the process in bytes, 0 if it can not be read.
+pub fn peak_rss_bytes() -> u64 {
+    PEAK_RSS_BEFORE_RESET
+        .load(Ordering::SeqCst)
+        .max(process_peak_rss())
+}
+
+/// resident set size of the process in bytes, 0 if it can not be read.
+pub fn rss_bytes() -> u64 {
+    #[cfg(target_os = "linux")]
+    let rss = procfs::process::Process::myself()
+        .and_then(|p| p.status())
+        .map_or(0, |s| s.vmrss.unwrap_or(0) * 1024);
+    #[cfg(not(target_os = "linux"))]
+    let rss = 0;
+    rss
+}
+
+// peak RSS since the last `reset_peak_rss`.
+fn process_peak_rss() -> u64 {
+    #[cfg(target_os = "linux")]
+    let peak = procfs::process::Process::myself()
+        .and_then(|p| p.status())
+        .map_or(0, |s| s.vmhwm.unwrap_or(0) * 1024);
+    #[cfg(not(target_os = "linux"))]
+    let peak = 0;
+    peak
+}
+
+// reset the peak RSS of the process to the current RSS, so that the peak of a stage can be read.
+// The peak is the one of the whole process, so this also resets it for the concurrent stages.
+fn reset_peak_rss() {
+    PEAK_RSS_BEFORE_RESET.fetch_max(process_peak_rss(), Ordering::SeqCst);
+    // writing 5 to clear_refs resets the peak, see proc(5).
+    #[cfg(target_os = "linux")]
+    let _ = std::fs::write("/proc/self/clear_refs", "5");
+}
+
+fn process_cpu_secs() -> f64 {
+    #[cfg(target_os = "linux")]
+    let secs = match (
+        procfs::process::Process::myself().and_then(|p| p.stat()),
+        procfs::ticks_per_second(),
+    ) {
+        (Ok(stat), Ok(ticks)) => (stat.utime + stat.stime) as f64 / ticks as f64,
+        _ => 0.0,
+    };
+    #[cfg(not(target_os = "linux"))]
+    let secs = 0.0;
+    secs
+}
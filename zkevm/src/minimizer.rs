@@ -0,0 +1,168 @@
+//! Shrink a failing batch to the smallest one which still reproduces the failure.
+
+use std::collections::{BTreeSet, HashSet};
+
+use eth_types::evm_types::OpcodeId;
+use eth_types::{ToAddress, Word};
+use ethers_core::types::Address;
+use halo2_proofs::dev::VerifyFailure;
+use types::eth::{AccountProofWrapper, BlockTrace};
+
+use crate::mock_report::{classify, sub_circuit_of};
+
+/// return what failed of the circuit: the sub circuit, the gate, lookup or column, and the
+/// region by name. Rows and region indices are left out, so that failures of a shrunk trace
+/// compare equal to the ones of the original trace.
+pub fn failure_signature(circuit: &str, failures: &[VerifyFailure]) -> BTreeSet<String> {
+    failures
+        .iter()
+        .map(|failure| {
+            let class = classify(failure);
+            let sub_circuit = sub_circuit_of(circuit, &class);
+            let region = class.region.as_deref().map_or("", region_name);
+            format!(
+                "[{sub_circuit}] {} {} in '{region}'",
+                class.kind, class.name
+            )
+        })
+        .collect()
+}
+
+// `Region 3 ('name')` -> `name`
+fn region_name(region: &str) -> &str {
+    region
+        .split_once('\'')
+        .and_then(|(_, rest)| rest.rsplit_once('\''))
+        .map_or(region, |(name, _)| name)
+}
+
+/// shrink the batch as long as `reproduces` holds. Blocks go first, then transactions, then
+/// storage proofs no remaining step refers to.
+/// `reproduces` is expected to hold for the given batch.
+pub fn minimize<F>(mut block_traces: Vec<BlockTrace>, mut reproduces: F) -> Vec<BlockTrace>
+where
+    F: FnMut(&[BlockTrace]) -> bool,
+{
+    // blocks
+    let mut idx = 0;
+    while block_traces.len() > 1 && idx < block_traces.len() {
+        let mut candidate = block_traces.clone();
+        candidate.remove(idx);
+        if reproduces(&candidate) {
+            log::info!("minimizer: removed block {}", idx);
+            block_traces = candidate;
+        } else {
+            idx += 1;
+        }
+    }
+
+    // transactions, in chunks halving down to single txs
+    for block_idx in 0..block_traces.len() {
+        let mut chunk = block_traces[block_idx].transactions.len().max(1);
+        while chunk > 0 {
+            let mut start = 0;
+            while start < block_traces[block_idx].transactions.len() {
+                let mut candidate = block_traces.clone();
+                remove_txs(&mut candidate[block_idx], start, chunk);
+                if reproduces(&candidate) {
+                    log::info!(
+                        "minimizer: removed txs {}..{} of block {}",
+                        start,
+                        start + chunk,
+                        block_idx
+                    );
+                    block_traces = candidate;
+                } else {
+                    start += chunk;
+                }
+            }
+            chunk /= 2;
+        }
+    }
+
+    // storage proofs
+    let mut candidate = block_traces.clone();
+    candidate.iter_mut().for_each(remove_unused_proofs);
+    if reproduces(&candidate) {
+        log::info!("minimizer: removed unused proofs");
+        block_traces = candidate;
+    }
+
+    block_traces
+}
+
+fn remove_txs(block_trace: &mut BlockTrace, start: usize, len: usize) {
+    let end = (start + len).min(block_trace.transactions.len());
+    block_trace.transactions.drain(start..end);
+    if start < block_trace.execution_results.len() {
+        let end = end.min(block_trace.execution_results.len());
+        block_trace.execution_results.drain(start..end);
+    }
+}
+
+/// drop the account and storage proofs of accounts and slots the remaining txs never touch.
+fn remove_unused_proofs(block_trace: &mut BlockTrace) {
+    let mut accounts: HashSet<Address> = HashSet::new();
+    let mut slots: HashSet<Word> = HashSet::new();
+    let add_account = |accounts: &mut HashSet<Address>, a: &AccountProofWrapper| {
+        accounts.extend(a.address);
+    };
+
+    add_account(&mut accounts, &block_trace.coinbase);
+    for tx in &block_trace.transactions {
+        accounts.insert(tx.from);
+        accounts.extend(tx.to);
+    }
+    for result in &block_trace.execution_results {
+        for a in result
+            .from
+            .iter()
+            .chain(result.to.iter())
+            .chain(result.account_created.iter())
+            .chain(result.account_after.iter())
+        {
+            add_account(&mut accounts, a);
+        }
+        for step in &result.exec_steps {
+            if let Some(proofs) = step.extra_data.as_ref().and_then(|d| d.proof_list.as_ref()) {
+                proofs.iter().for_each(|a| add_account(&mut accounts, a));
+            }
+            let stack = step.stack.as_deref().unwrap_or_default();
+            match step.op {
+                OpcodeId::SLOAD | OpcodeId::SSTORE => slots.extend(stack.last()),
+                OpcodeId::CALL
+                | OpcodeId::CALLCODE
+                | OpcodeId::DELEGATECALL
+                | OpcodeId::STATICCALL
+                | OpcodeId::BALANCE
+                | OpcodeId::EXTCODEHASH
+                | OpcodeId::EXTCODESIZE
+                | OpcodeId::EXTCODECOPY
+                | OpcodeId::SELFDESTRUCT => {
+                    let pos = match step.op {
+                        OpcodeId::CALL
+                        | OpcodeId::CALLCODE
+                        | OpcodeId::DELEGATECALL
+                        | OpcodeId::STATICCALL => 2,
+                        _ => 1,
+                    };
+                    if stack.len() >= pos {
+                        accounts.insert(stack[stack.len() - pos].to_address());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let storage_trace = &mut block_trace.storage_trace;
+    if let Some(proofs) = storage_trace.proofs.as_mut() {
+        proofs.retain(|address, _| accounts.contains(address));
+    }
+    storage_trace
+        .storage_proofs
+        .retain(|address, _| accounts.contains(address));
+    for account_slots in storage_trace.storage_proofs.values_mut() {
+        account_slots.retain(|key, _| slots.contains(key));
+    }
+}
@@ -0,0 +1,309 @@
+//! Machine readable report of the constraint failures found by `MockProver`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use halo2_proofs::dev::{FailureLocation, VerifyFailure};
+use halo2_proofs::halo2curves::bn256::Fr;
+use serde_derive::{Deserialize, Serialize};
+use zkevm_circuits::evm_circuit::witness::{Block, ExecStep, Transaction};
+use zkevm_circuits::evm_circuit::EvmCircuit;
+
+use crate::circuit::{circuits_params, SUB_CIRCUIT_NAMES};
+use crate::io::write_file_atomically;
+
+/// failures kept per group, the count of a group is always complete.
+const MAX_FAILURES_PER_GROUP: usize = 16;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MockProveReport {
+    pub circuit: String,
+    pub block_numbers: Vec<u64>,
+    pub failure_count: usize,
+    /// failures grouped by sub circuit, kind, name and region.
+    pub groups: Vec<FailureGroup>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FailureGroup {
+    pub sub_circuit: String,
+    pub kind: String,
+    /// name of the gate and constraint, lookup or column.
+    pub name: String,
+    pub region: Option<String>,
+    pub count: usize,
+    pub failures: Vec<FailureDetail>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FailureDetail {
+    /// row offset in the region, or absolute row outside of regions.
+    pub offset: Option<usize>,
+    pub step: Option<StepLocation>,
+    pub description: String,
+}
+
+/// the execution step a failing row belongs to.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StepLocation {
+    pub tx_index: usize,
+    pub step_index: usize,
+    pub rw_counter: usize,
+    pub pc: u64,
+    pub op: Option<String>,
+    pub execution_state: String,
+}
+
+/// the location independent part of a failure.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FailureClass {
+    pub kind: &'static str,
+    pub name: String,
+    pub region: Option<String>,
+    pub offset: Option<usize>,
+}
+
+pub fn classify(failure: &VerifyFailure) -> FailureClass {
+    let (region, offset) = match failure {
+        VerifyFailure::CellNotAssigned { region, offset, .. } => {
+            (Some(region.to_string()), usize::try_from(*offset).ok())
+        }
+        VerifyFailure::ConstraintNotSatisfied { location, .. }
+        | VerifyFailure::Lookup { location, .. }
+        | VerifyFailure::Permutation { location, .. } => match location {
+            FailureLocation::InRegion { region, offset } => {
+                (Some(region.to_string()), Some(*offset))
+            }
+            FailureLocation::OutsideRegion { row } => (None, Some(*row)),
+        },
+        #[allow(unreachable_patterns)]
+        _ => (None, None),
+    };
+    let (kind, name) = match failure {
+        VerifyFailure::CellNotAssigned { gate, column, .. } => {
+            ("cell_not_assigned", format!("{gate}, {column:?}"))
+        }
+        VerifyFailure::ConstraintNotSatisfied { constraint, .. } => {
+            ("constraint", constraint.to_string())
+        }
+        VerifyFailure::ConstraintPoisoned { constraint } => {
+            ("constraint_poisoned", constraint.to_string())
+        }
+        VerifyFailure::Lookup { lookup_index, .. } => ("lookup", format!("lookup {lookup_index}")),
+        VerifyFailure::Permutation { column, .. } => ("permutation", column.to_string()),
+        #[allow(unreachable_patterns)]
+        _ => ("other", failure.to_string()),
+    };
+    FailureClass {
+        kind,
+        name,
+        region,
+        offset,
+    }
+}
+
+impl MockProveReport {
+    pub fn new(
+        circuit: &str,
+        witness_block: &Block<Fr>,
+        block_numbers: Vec<u64>,
+        failures: &[VerifyFailure],
+    ) -> Self {
+        let mut groups: BTreeMap<_, FailureGroup> = BTreeMap::new();
+        // computed on the first evm failure
+        let mut evm_step_offsets = None;
+        for failure in failures {
+            let class = classify(failure);
+            let sub_circuit = sub_circuit_of(circuit, &class);
+            let step = match (sub_circuit.as_str(), class.offset) {
+                ("state", Some(offset)) => locate_state_row(witness_block, offset),
+                ("evm", Some(offset)) => {
+                    let offsets =
+                        evm_step_offsets.get_or_insert_with(|| step_offsets(witness_block));
+                    locate_evm_row(witness_block, offsets, offset)
+                }
+                _ => None,
+            };
+            let key = (
+                sub_circuit.clone(),
+                class.kind,
+                class.name.clone(),
+                class.region.clone(),
+            );
+            let group = groups.entry(key).or_insert_with(|| FailureGroup {
+                sub_circuit,
+                kind: class.kind.to_string(),
+                name: class.name.clone(),
+                region: class.region.clone(),
+                count: 0,
+                failures: Vec::new(),
+            });
+            group.count += 1;
+            if group.failures.len() < MAX_FAILURES_PER_GROUP {
+                group.failures.push(FailureDetail {
+                    offset: class.offset,
+                    step,
+                    description: failure.to_string(),
+                });
+            }
+        }
+
+        let mut groups: Vec<_> = groups.into_values().collect();
+        groups.sort_by(|a, b| b.count.cmp(&a.count));
+        Self {
+            circuit: circuit.to_string(),
+            block_numbers,
+            failure_count: failures.len(),
+            groups,
+        }
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        write_file_atomically(path.as_ref(), &serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// guess the sub circuit of a failure of the super circuit from the names of its region and gate.
+pub(crate) fn sub_circuit_of(circuit: &str, class: &FailureClass) -> String {
+    if circuit != "super" {
+        return circuit.to_string();
+    }
+    let texts = [class.region.as_deref().unwrap_or_default(), &class.name];
+    for text in texts {
+        let text = text.to_lowercase();
+        for token in text.split(|c: char| !c.is_ascii_alphanumeric()) {
+            let token = token.strip_suffix("circuit").unwrap_or(token);
+            let name = match token {
+                "execution" => "evm",
+                "rw" => "state",
+                "public" => "pi",
+                "zktrie" => "mpt",
+                _ => token,
+            };
+            if let Some(name) = SUB_CIRCUIT_NAMES.iter().find(|n| **n == name) {
+                return name.to_string();
+            }
+        }
+    }
+    "unknown".to_string()
+}
+
+/// the state circuit lays out the rws sorted by key, padded at the front up to `max_rws` rows.
+/// The rw counter of the row tells which step did the access.
+fn locate_state_row(witness_block: &Block<Fr>, offset: usize) -> Option<StepLocation> {
+    let rws = witness_block.rws.table_assignments();
+    let padding = circuits_params().max_rws.checked_sub(rws.len())?;
+    let rw_counter = rws.get(offset.checked_sub(padding)?)?.rw_counter();
+
+    let mut located = None;
+    for (tx_index, tx) in witness_block.txs.iter().enumerate() {
+        for (step_index, step) in tx.steps.iter().enumerate() {
+            if step.rw_counter > rw_counter {
+                return located;
+            }
+            located = Some(StepLocation::new(tx_index, step_index, step));
+        }
+    }
+    located
+}
+
+/// the evm circuit assigns the steps one after the other from the first row of its region, each
+/// as tall as its execution state. Return the first row of each step by tx and step index.
+pub fn step_offsets(witness_block: &Block<Fr>) -> Vec<Vec<usize>> {
+    let mut heights: HashMap<String, usize> = HashMap::new();
+    let mut offset = 0;
+    witness_block
+        .txs
+        .iter()
+        .map(|tx| {
+            tx.steps
+                .iter()
+                .map(|step| {
+                    let step_offset = offset;
+                    offset += *heights
+                        .entry(format!("{:?}", step.execution_state))
+                        .or_insert_with(|| step_height(step));
+                    step_offset
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// rows of a step, told by the rows the evm circuit requires with and without it.
+fn step_height(step: &ExecStep) -> usize {
+    let rows = |steps: Vec<ExecStep>| {
+        let block = Block::<Fr> {
+            txs: vec![Transaction {
+                steps,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        EvmCircuit::<Fr>::get_num_rows_required(&block)
+    };
+    rows(vec![step.clone()]).saturating_sub(rows(vec![]))
+}
+
+fn locate_evm_row(
+    witness_block: &Block<Fr>,
+    step_offsets: &[Vec<usize>],
+    offset: usize,
+) -> Option<StepLocation> {
+    let mut located = None;
+    for (tx_index, (tx, offsets)) in witness_block.txs.iter().zip(step_offsets).enumerate() {
+        for (step_index, (step, step_offset)) in tx.steps.iter().zip(offsets).enumerate() {
+            if *step_offset > offset {
+                return located;
+            }
+            located = Some(StepLocation::new(tx_index, step_index, step));
+        }
+    }
+    located
+}
+
+impl StepLocation {
+    fn new(tx_index: usize, step_index: usize, step: &ExecStep) -> Self {
+        Self {
+            tx_index,
+            step_index,
+            rw_counter: step.rw_counter,
+            pc: step.program_counter,
+            op: step.opcode.map(|op| format!("{op:?}")),
+            execution_state: format!("{:?}", step.execution_state),
+        }
+    }
+}
+
+/// error of a failed mock prove, carrying the report.
+#[derive(Debug)]
+pub struct MockProveFailed(pub MockProveReport);
+
+impl std::fmt::Display for MockProveFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "mock prove {} failed with {} failures:",
+            self.0.circuit, self.0.failure_count
+        )?;
+        for group in &self.0.groups {
+            write!(
+                f,
+                "\n  [{}] {} {}{} x{}",
+                group.sub_circuit,
+                group.kind,
+                group.name,
+                group
+                    .region
+                    .as_ref()
+                    .map(|r| format!(" in {r}"))
+                    .unwrap_or_default(),
+                group.count
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for MockProveFailed {}
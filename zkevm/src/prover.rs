@@ -2,19 +2,23 @@ use std::collections::HashMap;
 use std::io::Cursor;
 use std::path::PathBuf;
 
+use crate::checkpoint::{checkpoint_key, CheckpointStore, Stage};
 use crate::circuit::{
-    block_traces_to_witness_block, check_batch_capacity, SuperCircuit, TargetCircuit, AGG_DEGREE,
-    DEGREE,
+    calculate_row_usage_of_witness_block, check_batch_capacity, usable_rows, SuperCircuit,
+    TargetCircuit, AGG_DEGREE, DEGREE, WITNESS_CACHE,
 };
 use crate::io::{
     deserialize_fr_matrix, load_instances, serialize_fr_tensor, serialize_instance,
-    serialize_verify_circuit_final_pair, serialize_vk, write_verify_circuit_final_pair,
+    serialize_verify_circuit_final_pair, serialize_vk, write_file, write_verify_circuit_final_pair,
     write_verify_circuit_instance, write_verify_circuit_proof, write_verify_circuit_vk,
 };
+use crate::metrics::{rss_bytes, ProofProfile, ProveStage, PROVER_METRICS};
+use crate::mock_report::{MockProveFailed, MockProveReport};
 use crate::utils::{load_or_create_params, read_env_var};
 use crate::utils::{load_seed, metric_of_witness_block};
+use crate::verifier::Verifier;
 use anyhow::{bail, Error};
-use halo2_proofs::dev::MockProver;
+use halo2_proofs::dev::{MockProver, VerifyFailure};
 use halo2_proofs::halo2curves::bn256::{Bn256, Fr, G1Affine};
 use halo2_proofs::plonk::{
     create_proof, keygen_pk, keygen_pk2, keygen_vk, ProvingKey, VerifyingKey,
@@ -38,6 +42,7 @@ use rand_xorshift::XorShiftRng;
 use serde_derive::{Deserialize, Serialize};
 use types::base64;
 use types::eth::BlockTrace;
+use zkevm_circuits::evm_circuit::witness::Block;
 
 #[cfg(target_os = "linux")]
 extern crate procfs;
@@ -58,7 +63,7 @@ pub struct TargetCircuitProof {
     pub original_block_count: usize,
 }
 
-#[derive(Deserialize, Serialize, Debug, Default)]
+#[derive(Deserialize, Serialize, Debug, Default, Clone)]
 pub struct AggCircuitProof {
     #[serde(with = "base64")]
     pub proof: Vec<u8>,
@@ -91,10 +96,11 @@ impl AggCircuitProof {
         write_verify_circuit_proof(out_dir, &self.proof);
         write_verify_circuit_vk(out_dir, &self.vk);
 
-        out_dir.push("full_proof.data");
-        let mut fd = std::fs::File::create(out_dir.as_path()).unwrap();
-        out_dir.pop();
-        serde_json::to_writer_pretty(&mut fd, &self).unwrap()
+        write_file(
+            out_dir,
+            "full_proof.data",
+            &serde_json::to_vec_pretty(&self).unwrap(),
+        );
     }
 }
 
@@ -107,6 +113,10 @@ pub struct Prover {
     pub target_circuit_pks: HashMap<String, ProvingKey<G1Affine>>,
     pub agg_pk: Option<ProvingKey<G1Affine>>,
     pub debug_dir: String,
+    /// profile of the stages proved so far, see `take_profile`.
+    pub profile: ProofProfile,
+    /// rows each sub circuit may use, batches are truncated to fit. `usable_rows()` by default.
+    pub max_rows: usize,
     //pub target_circuit_vks: HashMap<String, ProvingKey<G1Affine>>,
 }
 
@@ -119,27 +129,27 @@ impl Prover {
             target_circuit_pks: Default::default(),
             agg_pk: None,
             debug_dir: Default::default(),
+            profile: Default::default(),
+            max_rows: usable_rows(),
         }
     }
 
+    /// return the profile of the stages proved since the last call.
+    pub fn take_profile(&mut self) -> ProofProfile {
+        std::mem::take(&mut self.profile)
+    }
+
     fn tick(desc: &str) {
-        #[cfg(target_os = "linux")]
-        let memory = match procfs::Meminfo::new() {
-            Ok(m) => m.mem_total - m.mem_free,
-            Err(_) => 0,
-        };
-        #[cfg(not(target_os = "linux"))]
-        let memory = 0;
-        log::debug!(
-            "memory usage when {}: {:?}GB",
-            desc,
-            memory / 1024 / 1024 / 1024
-        );
+        log::debug!("rss when {}: {}MB", desc, rss_bytes() / 1024 / 1024);
     }
 
     fn init_pk<C: TargetCircuit>(&mut self, circuit: &<C as TargetCircuit>::Inner) {
         Self::tick(&format!("before init pk of {}", C::name()));
-        let pk = keygen_pk2(&self.params, circuit)
+        let pk = self
+            .profile
+            .measure(ProveStage::Keygen, &C::name(), || {
+                keygen_pk2(&self.params, circuit)
+            })
             .unwrap_or_else(|e| panic!("failed to generate {} pk: {:?}", C::name(), e));
         self.target_circuit_pks.insert(C::name(), pk);
         Self::tick(&format!("after init pk of {}", C::name()));
@@ -183,7 +193,7 @@ impl Prover {
 
     pub fn debug_load_proved_circuit<C: TargetCircuit>(
         &mut self,
-        v: Option<&mut crate::verifier::Verifier>,
+        v: Option<&mut Verifier>,
     ) -> anyhow::Result<ProvedCircuit> {
         assert!(!self.debug_dir.is_empty());
         log::debug!("debug_load_proved_circuit {}", C::name());
@@ -196,6 +206,50 @@ impl Prover {
         self.convert_target_proof::<C>(&proof)
     }
 
+    /// create the agg proof of the batch, skipping the stages already checkpointed in `store`.
+    /// Checkpointed proofs are verified with `v`, and redone if invalid.
+    pub fn resume(
+        &mut self,
+        block_traces: &[BlockTrace],
+        store: &CheckpointStore,
+        v: &mut Verifier,
+    ) -> anyhow::Result<AggCircuitProof> {
+        let key = checkpoint_key(block_traces);
+        log::info!("resume proving batch {}", key);
+
+        if let Some(proof) = store.load::<AggCircuitProof>(&key, Stage::Agg) {
+            match v.verify_agg_circuit_proof(proof.clone()) {
+                Ok(()) => return Ok(proof),
+                Err(e) => {
+                    log::warn!("invalid agg proof checkpoint of {}: {}", key, e);
+                    store.remove(&key, Stage::Agg);
+                }
+            }
+        }
+
+        let mut target_proof = store.load::<TargetCircuitProof>(&key, Stage::Target);
+        if let Some(proof) = &target_proof {
+            if let Err(e) = v.verify_target_circuit_proof::<SuperCircuit>(proof) {
+                log::warn!("invalid target proof checkpoint of {}: {}", key, e);
+                store.remove(&key, Stage::Target);
+                target_proof = None;
+            }
+        }
+        let target_proof = match target_proof {
+            Some(proof) => proof,
+            None => {
+                let proof = self.create_target_circuit_proof_batch::<SuperCircuit>(block_traces)?;
+                store.save(&key, Stage::Target, &proof)?;
+                proof
+            }
+        };
+
+        let circuit_results = vec![self.convert_target_proof::<SuperCircuit>(&target_proof)?];
+        let agg_proof = self.create_agg_circuit_proof_impl(circuit_results)?;
+        store.save(&key, Stage::Agg, &agg_proof)?;
+        Ok(agg_proof)
+    }
+
     pub fn prove_circuit<C: TargetCircuit>(
         &mut self,
         block_traces: &[BlockTrace],
@@ -329,11 +383,13 @@ impl Prover {
 
         if self.agg_pk.is_none() {
             log::info!("generate agg pk: begin");
-            let verify_circuit_vk =
-                keygen_vk(&self.agg_params, &verify_circuit).expect("keygen_vk should not fail");
-            log::info!("generate agg pk: vk done");
-            let verify_circuit_pk = keygen_pk(&self.agg_params, verify_circuit_vk, &verify_circuit)
-                .expect("keygen_pk should not fail");
+            let verify_circuit_pk = self.profile.measure(ProveStage::Keygen, "agg", || {
+                let verify_circuit_vk = keygen_vk(&self.agg_params, &verify_circuit)
+                    .expect("keygen_vk should not fail");
+                log::info!("generate agg pk: vk done");
+                keygen_pk(&self.agg_params, verify_circuit_vk, &verify_circuit)
+                    .expect("keygen_pk should not fail")
+            });
             self.agg_pk = Some(verify_circuit_pk);
             log::info!("init_agg_pk: done");
         } else {
@@ -360,14 +416,16 @@ impl Prover {
             log::info!("mock prove agg circuit done");
         }
         log::info!("create agg proof");
-        create_proof::<KZGCommitmentScheme<_>, ProverGWC<_>, _, _, _, _>(
-            &self.agg_params,
-            self.agg_pk.as_ref().unwrap(),
-            &[verify_circuit],
-            instances_slice,
-            self.rng.clone(),
-            &mut transcript,
-        )?;
+        self.profile.measure(ProveStage::Aggregation, "agg", || {
+            create_proof::<KZGCommitmentScheme<_>, ProverGWC<_>, _, _, _, _>(
+                &self.agg_params,
+                self.agg_pk.as_ref().unwrap(),
+                &[verify_circuit],
+                instances_slice,
+                self.rng.clone(),
+                &mut transcript,
+            )
+        })?;
         log::info!(
             "create agg proof done, block proved {}/{}",
             circuit_results[0].proved_block_count,
@@ -407,22 +465,15 @@ impl Prover {
         log::info!("rows needed {:?}", C::estimate_rows(block_traces));
         let original_block_len = block_traces.len();
         let mut block_traces = block_traces.to_vec();
-        check_batch_capacity(&mut block_traces)?;
-        let witness_block = block_traces_to_witness_block(&block_traces)?;
+        check_batch_capacity(&mut block_traces, usable_rows())?;
+        let witness_block = WITNESS_CACHE.witness_block(&block_traces)?;
         log::info!(
             "mock proving batch of len {}, batch metric {:?}",
             original_block_len,
             metric_of_witness_block(&witness_block)
         );
-        let (circuit, instance) = C::from_witness_block(&witness_block)?;
-        let prover = MockProver::<Fr>::run(*DEGREE as u32, &circuit, instance)?;
-        if let Err(errs) = prover.verify_par() {
-            log::error!("err num: {}", errs.len());
-            for err in &errs {
-                log::error!("{}", err);
-            }
-            bail!("{:?}", errs);
-        }
+        let errs = Self::mock_verify_witness_block::<C>(&witness_block)?;
+        Self::check_mock_failures::<C>(&witness_block, &block_traces, &errs)?;
         log::info!(
             "mock prove {} done. block proved {}/{}, batch metric: {:?}",
             C::name(),
@@ -433,6 +484,44 @@ impl Prover {
         Ok(())
     }
 
+    /// mock prove the batch and return the constraint failures, empty if it is satisfied.
+    /// Errors are returned for batches which can not be turned into a circuit at all.
+    pub fn mock_prove_failures<C: TargetCircuit>(
+        block_traces: &[BlockTrace],
+    ) -> anyhow::Result<Vec<VerifyFailure>> {
+        let mut block_traces = block_traces.to_vec();
+        check_batch_capacity(&mut block_traces, usable_rows())?;
+        let witness_block = WITNESS_CACHE.witness_block(&block_traces)?;
+        Self::mock_verify_witness_block::<C>(&witness_block)
+    }
+
+    /// turn the failures into a `MockProveFailed` error with the report.
+    fn check_mock_failures<C: TargetCircuit>(
+        witness_block: &Block<Fr>,
+        block_traces: &[BlockTrace],
+        errs: &[VerifyFailure],
+    ) -> anyhow::Result<()> {
+        if errs.is_empty() {
+            return Ok(());
+        }
+        let block_numbers = block_traces
+            .iter()
+            .filter_map(|b| b.header.number.map(|n| n.as_u64()))
+            .collect();
+        let report = MockProveReport::new(&C::name(), witness_block, block_numbers, errs);
+        let err = MockProveFailed(report);
+        log::error!("{}", err);
+        Err(err.into())
+    }
+
+    fn mock_verify_witness_block<C: TargetCircuit>(
+        witness_block: &Block<Fr>,
+    ) -> anyhow::Result<Vec<VerifyFailure>> {
+        let (circuit, instance) = C::from_witness_block(witness_block)?;
+        let prover = MockProver::<Fr>::run(*DEGREE as u32, &circuit, instance)?;
+        Ok(prover.verify_par().err().unwrap_or_default())
+    }
+
     pub fn create_target_circuit_proof<C: TargetCircuit>(
         &mut self,
         block_trace: &BlockTrace,
@@ -446,14 +535,35 @@ impl Prover {
     ) -> anyhow::Result<TargetCircuitProof, Error> {
         let original_block_count = block_traces.len();
         let mut block_traces = block_traces.to_vec();
-        check_batch_capacity(&mut block_traces)?;
-        let witness_block = block_traces_to_witness_block(&block_traces)?;
+        self.profile
+            .measure(ProveStage::CapacityCheck, &C::name(), || {
+                check_batch_capacity(&mut block_traces, self.max_rows)
+            })?;
+        self.create_target_circuit_proof_of_checked_batch::<C>(&block_traces, original_block_count)
+    }
+
+    /// create the target proof of a batch which already passed `check_batch_capacity`.
+    fn create_target_circuit_proof_of_checked_batch<C: TargetCircuit>(
+        &mut self,
+        block_traces: &[BlockTrace],
+        original_block_count: usize,
+    ) -> anyhow::Result<TargetCircuitProof, Error> {
+        let name = C::name();
+        let witness_block = self.profile.measure(ProveStage::Witness, &name, || {
+            WITNESS_CACHE.witness_block(block_traces)
+        })?;
         log::info!(
             "proving batch of len {}, batch metric {:?}",
             original_block_count,
             metric_of_witness_block(&witness_block)
         );
-        let (circuit, instance) = C::from_witness_block(&witness_block)?;
+        match calculate_row_usage_of_witness_block(&witness_block) {
+            Ok(rows) => PROVER_METRICS.record_row_usage(&rows),
+            Err(e) => log::warn!("failed to calculate row usage: {}", e),
+        }
+        let (circuit, instance) = self.profile.measure(ProveStage::Synthesis, &name, || {
+            C::from_witness_block(&witness_block)
+        })?;
         let mut transcript = PoseidonWrite::<_, G1Affine, Challenge255<_>>::init(vec![]);
 
         let instance_slice = instance.iter().map(|x| &x[..]).collect::<Vec<_>>();
@@ -470,13 +580,8 @@ impl Prover {
         if *MOCK_PROVE {
             log::info!("mock prove {} start", C::name());
             let prover = MockProver::<Fr>::run(*DEGREE as u32, &circuit, instance.clone())?;
-            if let Err(errs) = prover.verify_par() {
-                log::error!("err num: {}", errs.len());
-                for err in &errs {
-                    log::error!("{}", err);
-                }
-                bail!("{:#?}", errs);
-            }
+            let errs = prover.verify_par().err().unwrap_or_default();
+            Self::check_mock_failures::<C>(&witness_block, block_traces, &errs)?;
             log::info!("mock prove {} done", C::name());
         }
 
@@ -485,14 +590,16 @@ impl Prover {
             self.init_pk::<C>(&C::empty());
         }
         let pk = &self.target_circuit_pks[&C::name()];
-        create_proof::<KZGCommitmentScheme<_>, ProverGWC<_>, _, _, _, _>(
-            &self.params,
-            pk,
-            &[circuit],
-            public_inputs,
-            self.rng.clone(),
-            &mut transcript,
-        )?;
+        self.profile.measure(ProveStage::CreateProof, &name, || {
+            create_proof::<KZGCommitmentScheme<_>, ProverGWC<_>, _, _, _, _>(
+                &self.params,
+                pk,
+                &[circuit],
+                public_inputs,
+                self.rng.clone(),
+                &mut transcript,
+            )
+        })?;
         info!(
             "Create {} proof of block {} ... block {} Successfully!",
             C::name(),
@@ -501,7 +608,6 @@ impl Prover {
         );
         let instance_bytes = serialize_instance(&instance);
         let proof = transcript.finalize();
-        let name = C::name();
         log::debug!(
             "{} circuit: proof {:?}, instance len {}",
             name,
@@ -517,17 +623,18 @@ impl Prover {
             proved_block_count: witness_block.context.ctxs.len(),
         };
         if !self.debug_dir.is_empty() {
+            let mut folder = PathBuf::from(&self.debug_dir);
             // write vk
-            let mut fd = std::fs::File::create(format!("{}/{}.vk", self.debug_dir, &name)).unwrap();
-            pk.get_vk().write(&mut fd, SerdeFormat::Processed).unwrap();
-            drop(fd);
+            let mut vk = Vec::new();
+            pk.get_vk().write(&mut vk, SerdeFormat::Processed).unwrap();
+            write_file(&mut folder, &format!("{name}.vk"), &vk);
 
             // write proof
-            //let mut folder = PathBuf::from_str(&self.debug_dir).unwrap();
-            //write_file(&mut folder, &format!("{}.proof", name), &proof);
-            let output_file = format!("{}/{}_proof.json", self.debug_dir, name);
-            let mut fd = std::fs::File::create(output_file).unwrap();
-            serde_json::to_writer_pretty(&mut fd, &target_proof).unwrap();
+            write_file(
+                &mut folder,
+                &format!("{name}_proof.json"),
+                &serde_json::to_vec_pretty(&target_proof).unwrap(),
+            );
         }
         Ok(target_proof)
     }
@@ -1,4 +1,5 @@
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
+use blake2::{Blake2s256, Digest};
 use halo2_proofs::arithmetic::Field;
 use halo2_proofs::halo2curves::bn256::{Bn256, Fr};
 use halo2_proofs::halo2curves::FieldExt;
@@ -7,10 +8,11 @@ use halo2_proofs::SerdeFormat;
 use halo2_proofs::poly::kzg::commitment::ParamsKZG;
 use rand::rngs::OsRng;
 use std::fs::{self, metadata, File};
-use std::io::{BufReader, Read, Write};
+use std::io::{self, BufReader, Read, Write};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use types::eth::{BlockTrace, BlockTraceJsonRpcResult};
+use types::compact;
+use types::eth::{block_trace_from_json_rpc_reader, block_trace_from_reader, BlockTrace};
 use zkevm_circuits::witness;
 
 pub(crate) const DEFAULT_SERDE_FORMAT: SerdeFormat = SerdeFormat::RawBytesUnchecked;
@@ -149,25 +151,60 @@ pub fn create_seed(seed_path: &str) -> Result<[u8; 16]> {
 
 /// get a block-result from file
 pub fn get_block_trace_from_file<P: AsRef<Path>>(path: P) -> BlockTrace {
-    let mut buffer = Vec::new();
-    let mut f = File::open(&path).unwrap();
-    f.read_to_end(&mut buffer).unwrap();
-
-    serde_json::from_slice::<BlockTrace>(&buffer).unwrap_or_else(|e1| {
-        serde_json::from_slice::<BlockTraceJsonRpcResult>(&buffer)
-            .map_err(|e2| {
-                panic!(
-                    "unable to load BlockTrace from {:?}, {:?}, {:?}",
-                    path.as_ref(),
-                    e1,
-                    e2
-                )
-            })
-            .unwrap()
-            .result
+    try_get_block_trace_from_file(path).unwrap_or_else(|e| panic!("{e:?}"))
+}
+
+/// get a block-result from a JSON, JSON-RPC response or compact trace file.
+pub fn try_get_block_trace_from_file<P: AsRef<Path>>(path: P) -> Result<BlockTrace> {
+    let path = path.as_ref();
+    let open = || File::open(path).with_context(|| format!("unable to open {path:?}"));
+
+    let mut magic = [0u8; 4];
+    if open()?.read_exact(&mut magic).is_ok() && compact::is_compact(&magic) {
+        let buf = fs::read(path)?;
+        return compact::decode_block_trace(&buf)
+            .with_context(|| format!("unable to load compact BlockTrace from {path:?}"));
+    }
+
+    block_trace_from_reader(open()?).or_else(|e1| {
+        block_trace_from_json_rpc_reader(open()?).map_err(|e2| {
+            anyhow!(
+                "unable to load BlockTrace from {:?}, {:?}, {:?}",
+                path,
+                e1,
+                e2
+            )
+        })
     })
 }
 
+/// return a hex digest identifying the traces, stable across processes.
+pub fn hash_block_traces(block_traces: &[BlockTrace]) -> String {
+    // traces serialize their maps in key order, so they are written straight into the digest.
+    let mut hasher = Blake2s256::new();
+    serde_json::to_writer(DigestWriter(&mut hasher), block_traces)
+        .expect("writing to a digest should not fail");
+    hex::encode(hasher.finalize())
+}
+
+/// return a hex digest of the given bytes.
+pub fn hash_bytes(buf: &[u8]) -> String {
+    hex::encode(Blake2s256::digest(buf))
+}
+
+struct DigestWriter<'a, D: Digest>(&'a mut D);
+
+impl<D: Digest> Write for DigestWriter<'_, D> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
 pub fn read_env_var<T: Clone + FromStr>(var_name: &'static str, default: T) -> T {
     std::env::var(var_name)
         .map(|s| s.parse::<T>().unwrap_or_else(|_| default.clone()))
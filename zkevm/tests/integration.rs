@@ -9,7 +9,7 @@ use zkevm::{
 };
 
 mod test_util;
-use test_util::{init, load_block_traces_for_test, PARAMS_DIR, SEED_PATH};
+use test_util::{init, load_batch_traces, load_block_traces_for_test, PARAMS_DIR, SEED_PATH};
 
 use once_cell::sync::Lazy;
 use zkevm::utils::read_env_var;
@@ -42,7 +42,7 @@ fn test_load_params() {
 
 #[test]
 fn estimate_circuit_rows() {
-    use zkevm::circuit::{self, TargetCircuit};
+    use zkevm::circuit::CIRCUIT_REGISTRY;
 
     init();
 
@@ -50,24 +50,812 @@ fn estimate_circuit_rows() {
 
     log::info!("estimating used rows for batch");
     for circuit in CIRCUIT.split(",") {
-        let rows = match circuit {
-            "evm" => circuit::EvmCircuit::estimate_rows(&block_trace),
-            "state" => circuit::StateCircuit::estimate_rows(&block_trace),
-            "zktrie" => circuit::ZktrieCircuit::estimate_rows(&block_trace),
-            "poseidon" => circuit::PoseidonCircuit::estimate_rows(&block_trace),
-            "super" => circuit::SuperCircuit::estimate_rows(&block_trace),
-            _ => {
-                unimplemented!("invalid circuit: {:?}", circuit);
+        let rows = CIRCUIT_REGISTRY
+            .get(circuit)
+            .unwrap_or_else(|| unimplemented!("invalid circuit: {:?}", circuit))
+            .estimate_rows(&block_trace);
+        log::info!("{} circuit: {:?}", circuit, rows);
+    }
+}
+
+#[test]
+fn test_compact_trace_roundtrip() {
+    use types::compact;
+
+    init();
+
+    let (_, mut block_traces) = load_batch_traces("./tests/traces/kroma");
+    block_traces.extend(load_block_traces_for_test().1);
+    for block_trace in block_traces {
+        let json = serde_json::to_vec(&block_trace).unwrap();
+        let buf = compact::encode_block_trace(&block_trace).unwrap();
+        log::info!("json {} bytes, compact {} bytes", json.len(), buf.len());
+
+        let decoded = compact::decode_block_trace(&buf).unwrap();
+        assert_eq!(
+            serde_json::to_value(&decoded).unwrap(),
+            serde_json::to_value(&block_trace).unwrap()
+        );
+        assert_eq!(compact::encode_block_trace(&decoded).unwrap(), buf);
+
+        let storage_trace = compact::encode_storage_trace(&block_trace.storage_trace).unwrap();
+        assert!(compact::decode_block_trace(&storage_trace).is_err());
+    }
+}
+
+#[test]
+fn test_load_broken_trace() {
+    use zkevm::utils::try_get_block_trace_from_file;
+
+    init();
+
+    assert!(try_get_block_trace_from_file("./tests/traces/kroma/user_deposit.json").is_ok());
+    assert!(try_get_block_trace_from_file("./tests/traces/kroma/missing.json").is_err());
+    let file = std::env::temp_dir().join(format!("kroma_broken_{}.json", std::process::id()));
+    for broken in [&b"{\"chainID\": "[..], &types::compact::MAGIC[..]] {
+        std::fs::write(&file, broken).unwrap();
+        assert!(try_get_block_trace_from_file(&file).is_err());
+    }
+    std::fs::remove_file(file).unwrap();
+}
+
+#[test]
+fn test_parse_memory() {
+    use types::eth::{block_trace_from_reader, block_trace_from_reader_skipping_memory};
+
+    init();
+
+    let (_, block_traces) = load_batch_traces("./tests/traces/kroma");
+    let mut value = serde_json::to_value(&block_traces[0]).unwrap();
+    value["executionResults"][0]["structLogs"][0]["memory"] = serde_json::json!(["0x1", "0x2"]);
+    let json = serde_json::to_vec(&value).unwrap();
+
+    let parsed = block_trace_from_reader(&json[..]).unwrap();
+    assert_eq!(
+        parsed.execution_results[0].exec_steps[0].memory,
+        Some(vec![1.into(), 2.into()])
+    );
+    assert_eq!(serde_json::to_value(&parsed).unwrap(), value);
+
+    let skipped = block_trace_from_reader_skipping_memory(&json[..]).unwrap();
+    assert_eq!(skipped.execution_results[0].exec_steps[0].memory, None);
+    // the default parse is lossless again afterwards
+    let parsed = block_trace_from_reader(&json[..]).unwrap();
+    assert!(parsed.execution_results[0].exec_steps[0].memory.is_some());
+}
+
+#[test]
+fn test_compact_trace_v1() {
+    use flate2::read::DeflateDecoder;
+    use flate2::write::DeflateEncoder;
+    use flate2::Compression;
+    use std::io::{Read, Write};
+    use types::compact;
+
+    init();
+
+    let (_, block_traces) = load_batch_traces("./tests/traces/kroma");
+    let mut block_trace = block_traces[0].clone();
+    block_trace.history_hashes = vec![];
+    let buf = compact::encode_block_trace(&block_trace).unwrap();
+    let header_len = compact::MAGIC.len() + 3;
+
+    // a v1 block trace is a v2 one without the history hashes, the trailing empty vec
+    let mut payload = vec![];
+    DeflateDecoder::new(&buf[header_len..])
+        .read_to_end(&mut payload)
+        .unwrap();
+    let history_hashes_len = payload.split_off(payload.len() - 8);
+    assert_eq!(history_hashes_len, 0u64.to_le_bytes());
+    let mut v1 = buf[..header_len].to_vec();
+    v1[4..6].copy_from_slice(&1u16.to_le_bytes());
+    let mut encoder = DeflateEncoder::new(v1, Compression::default());
+    encoder.write_all(&payload).unwrap();
+    let v1 = encoder.finish().unwrap();
+
+    assert_eq!(compact::version(&v1).unwrap(), 1);
+    let decoded = compact::decode_block_trace(&v1).unwrap();
+    assert_eq!(
+        serde_json::to_value(&decoded).unwrap(),
+        serde_json::to_value(&block_trace).unwrap()
+    );
+
+    let mut v0 = v1.clone();
+    v0[4..6].copy_from_slice(&0u16.to_le_bytes());
+    assert!(matches!(
+        compact::decode_block_trace(&v0),
+        Err(compact::Error::UnsupportedVersion(0))
+    ));
+}
+
+#[test]
+fn test_record_fixtures() {
+    use zkevm::fixture::{FixtureManifest, FixtureRecorder};
+
+    init();
+
+    let dir = std::env::temp_dir().join(format!("kroma_fixtures_{}", std::process::id()));
+    let (_, block_traces) = load_batch_traces("./tests/traces/kroma");
+    let recorder = FixtureRecorder::new(&dir, vec!["memory".to_string()]).unwrap();
+    for block_trace in &block_traces {
+        recorder.record(block_trace, Some(1)).unwrap();
+    }
+    // recording again replaces the fixture
+    recorder.record(&block_traces[0], Some(1)).unwrap();
+
+    let manifest = FixtureManifest::load(&dir).unwrap().unwrap();
+    assert_eq!(manifest.fixtures.len(), block_traces.len());
+    let (names, recorded) = load_batch_traces(dir.to_str().unwrap());
+    assert_eq!(names.len(), block_traces.len());
+    for (recorded, block_trace) in recorded.iter().zip(&block_traces) {
+        assert_eq!(recorded.header.number, block_trace.header.number);
+        assert_eq!(recorded.transactions.len(), block_trace.transactions.len());
+    }
+
+    // required fields can not be stripped
+    let recorder = FixtureRecorder::new(&dir, vec!["header".to_string()]).unwrap();
+    assert!(recorder.record(&block_traces[0], None).is_err());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_checkpoint_store() {
+    use serde_json::{json, Value};
+    use zkevm::checkpoint::{checkpoint_key, CheckpointStore, Stage};
+
+    init();
+
+    let dir = std::env::temp_dir().join(format!("kroma_checkpoints_{}", std::process::id()));
+    let store = CheckpointStore::new(&dir).unwrap();
+    let (_, block_traces) = load_batch_traces("./tests/traces/kroma");
+    let key = checkpoint_key(&block_traces);
+    assert_ne!(key, checkpoint_key(&block_traces[..1]));
+
+    assert!(store.load::<Value>(&key, Stage::Target).is_none());
+    let payload = json!({"proof": "AAEC", "proved_block_count": 2});
+    store.save(&key, Stage::Target, &payload).unwrap();
+    assert_eq!(
+        store.load::<Value>(&key, Stage::Target),
+        Some(payload.clone())
+    );
+    assert!(!store
+        .path(&key, Stage::Target)
+        .with_extension("json.tmp")
+        .exists());
+
+    // a checkpoint of another stage or batch is not picked up
+    std::fs::copy(
+        store.path(&key, Stage::Target),
+        store.path(&key, Stage::Agg),
+    )
+    .unwrap();
+    assert!(store.load::<Value>(&key, Stage::Agg).is_none());
+    assert!(!store.path(&key, Stage::Agg).exists());
+
+    // a tampered payload fails the digest and is removed
+    let path = store.path(&key, Stage::Target);
+    let tampered = std::fs::read_to_string(&path)
+        .unwrap()
+        .replace("\"proved_block_count\":2", "\"proved_block_count\":1");
+    std::fs::write(&path, tampered).unwrap();
+    assert!(store.load::<Value>(&key, Stage::Target).is_none());
+    assert!(!path.exists());
+
+    // so is a half-written one
+    store.save(&key, Stage::Target, &payload).unwrap();
+    let buf = std::fs::read(&path).unwrap();
+    std::fs::write(&path, &buf[..buf.len() / 2]).unwrap();
+    assert!(store.load::<Value>(&key, Stage::Target).is_none());
+    assert!(!path.exists());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_row_estimate_calibration() {
+    use zkevm::circuit::{
+        calculate_row_usage_of_trace, estimate_row_usage_of_trace, SUB_CIRCUIT_NAMES,
+    };
+
+    init();
+
+    let (names, block_traces) = load_batch_traces("./tests/traces");
+    for (name, block_trace) in names.iter().zip(block_traces.iter()) {
+        let exact = calculate_row_usage_of_trace(block_trace).unwrap();
+        let estimate = estimate_row_usage_of_trace(block_trace);
+        for ((circuit, exact), estimate) in SUB_CIRCUIT_NAMES.iter().zip(&exact).zip(&estimate) {
+            log::info!(
+                "{name} {circuit}: exact {exact}, estimate {estimate}, ratio {:.2}",
+                *estimate as f64 / (*exact).max(1) as f64
+            );
+            assert!(
+                estimate >= exact,
+                "{name}: estimate of {circuit} rows {estimate} is below {exact}"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_row_estimate_of_huge_sizes() {
+    use eth_types::evm_types::OpcodeId;
+    use ethers_core::types::U256;
+    use types::eth::ExecStep;
+    use zkevm::circuit::{estimate_row_usage_of_tx, usable_rows, SUB_CIRCUIT_NAMES};
+    use zkevm::utils::get_block_trace_from_file;
+
+    init();
+
+    let block_trace = get_block_trace_from_file("./tests/traces/kroma/multiple_transfers_0.json");
+    let mut execution_result = block_trace.execution_results[0].clone();
+    // hashing 2^40 bytes, the size of the top of the stack is the offset
+    execution_result.exec_steps.push(ExecStep {
+        pc: 0,
+        op: OpcodeId::SHA3,
+        gas: 0,
+        gas_cost: 0,
+        refund: 0,
+        depth: 1,
+        error: None,
+        stack: Some(vec![U256::one() << 40, U256::zero()]),
+        memory: None,
+        storage: None,
+        extra_data: None,
+    });
+    let rows = estimate_row_usage_of_tx(&block_trace.transactions[0], &execution_result);
+    let keccak = SUB_CIRCUIT_NAMES
+        .iter()
+        .position(|n| *n == "keccak")
+        .unwrap();
+    assert!(rows[keccak] > usable_rows());
+}
+
+#[test]
+fn test_check_capacity() {
+    use zkevm::circuit::{check_capacity, usable_rows, SUB_CIRCUIT_NAMES};
+
+    init();
+
+    let (_, block_traces) = load_batch_traces("./tests/traces/kroma");
+    let check = check_capacity(&block_traces[0], &[], usable_rows()).unwrap();
+    assert!(check.accepted);
+    assert_eq!(check.usage, check.trace_usage);
+
+    let check = check_capacity(&block_traces[0], &check.usage, usable_rows()).unwrap();
+    assert_eq!(
+        check.usage,
+        check.trace_usage.iter().map(|r| r * 2).collect::<Vec<_>>()
+    );
+
+    // nothing fits into a full circuit
+    let full = vec![usable_rows(); SUB_CIRCUIT_NAMES.len()];
+    let check = check_capacity(&block_traces[0], &full, usable_rows()).unwrap();
+    assert!(!check.accepted);
+    assert!(check.headroom.iter().all(|h| *h <= 0));
+
+    assert!(check_capacity(&block_traces[0], &[0], usable_rows()).is_err());
+}
+
+#[test]
+fn test_circuit_registry() {
+    use std::collections::HashSet;
+    use zkevm::circuit::{AGG_PROOF_ID, CIRCUIT_REGISTRY};
+
+    let entries = CIRCUIT_REGISTRY.entries();
+    assert_eq!(
+        CIRCUIT_REGISTRY.names(),
+        vec!["evm", "state", "super", "zktrie", "poseidon"]
+    );
+    let ids: HashSet<_> = entries.iter().map(|e| e.id).collect();
+    assert_eq!(ids.len(), entries.len());
+    assert!(!ids.contains(&AGG_PROOF_ID));
+    for entry in entries {
+        assert_eq!(CIRCUIT_REGISTRY.by_id(entry.id).unwrap().name, entry.name);
+        assert_eq!(CIRCUIT_REGISTRY.get(&entry.name).unwrap().id, entry.id);
+    }
+    assert!(CIRCUIT_REGISTRY.get("agg").is_none());
+}
+
+#[test]
+fn test_empty_circuits() {
+    use zkevm::circuit::{resolve_chain_id, CHAIN_ID, CIRCUIT_REGISTRY};
+
+    init();
+
+    // keygen builds the circuits of an empty batch, which is of the configured chain
+    assert_eq!(resolve_chain_id(&[]), Ok((*CHAIN_ID).into()));
+    for entry in CIRCUIT_REGISTRY.entries() {
+        log::info!("building the empty {}", entry.name);
+        entry.empty();
+    }
+}
+
+#[test]
+fn test_prover_metrics() {
+    use std::time::Duration;
+    use zkevm::circuit::{usable_rows, SUB_CIRCUIT_NAMES};
+    use zkevm::metrics::{ProveStage, ProverMetrics, ROW_USAGE_BUCKETS};
+
+    let metrics = ProverMetrics::default();
+    assert_eq!(metrics.measure(ProveStage::Witness, "super", || 1), 1);
+    metrics.record_stage(ProveStage::Witness, "super", Duration::from_secs(2));
+    let stages = metrics.stages();
+    assert_eq!(stages.len(), 1);
+    let (stage, circuit, durations) = &stages[0];
+    assert_eq!((*stage, circuit.as_str()), (ProveStage::Witness, "super"));
+    assert_eq!(durations.count, 2);
+    assert_eq!(durations.max, Duration::from_secs(2));
+
+    let mut rows = vec![0; SUB_CIRCUIT_NAMES.len()];
+    rows[0] = usable_rows();
+    metrics.record_row_usage(&rows);
+    let row_usage = metrics.row_usage();
+    assert_eq!(row_usage.len(), SUB_CIRCUIT_NAMES.len());
+    assert_eq!(row_usage[0].1.buckets[ROW_USAGE_BUCKETS.len() - 1], 1);
+    assert_eq!(row_usage[0].1.buckets[0], 0);
+    assert_eq!(row_usage[1].1.buckets, [1; ROW_USAGE_BUCKETS.len()]);
+}
+
+#[test]
+fn test_proof_profile() {
+    use zkevm::metrics::{ProofProfile, ProveStage};
+
+    let mut profile = ProofProfile::default();
+    profile.measure(ProveStage::TraceParse, "super", || ());
+    let buf = profile.measure(ProveStage::Witness, "super", || vec![1u8; 1 << 20]);
+    assert_eq!(buf.len(), 1 << 20);
+    assert_eq!(profile.stages.len(), 2);
+    assert!(profile.wall_secs() >= profile.stages[1].wall_secs);
+    assert!(profile.stages.iter().all(|s| s.cpu_secs >= 0.0));
+
+    let json = serde_json::to_value(&profile).unwrap();
+    assert_eq!(json["stages"][0]["stage"], "trace_parse");
+    assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+}
+
+#[test]
+fn test_write_file_atomically() {
+    use zkevm::io::{write_file, write_file_atomically};
+
+    let mut dir = std::env::temp_dir().join(format!("kroma_atomic_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("agg.proof");
+    write_file_atomically(&path, b"old").unwrap();
+    write_file_atomically(&path, b"new").unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), b"new");
+
+    write_file(&mut dir, "profile.json", b"{}");
+    let mut names: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(names, vec!["agg.proof", "profile.json"]);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_witness_cache() {
+    use ethers_core::types::U256;
+    use zkevm::circuit::WitnessCache;
+    use zkevm::utils::get_block_trace_from_file;
+
+    init();
+
+    let block_trace = get_block_trace_from_file("./tests/traces/kroma/user_deposit.json");
+    let mut other_chain = block_trace.clone();
+    other_chain.chain_id = U256::from(902);
+    assert_ne!(
+        WitnessCache::key(std::slice::from_ref(&block_trace)),
+        WitnessCache::key(&[other_chain])
+    );
+    // the key does not depend on the order the maps of the trace are iterated in
+    assert_eq!(
+        WitnessCache::key(std::slice::from_ref(&block_trace)),
+        WitnessCache::key(&[
+            serde_json::from_value(serde_json::to_value(&block_trace).unwrap()).unwrap()
+        ])
+    );
+
+    // a dir under a file can not be created, which disables the disk cache
+    let file = std::env::temp_dir().join(format!("kroma_witness_{}", std::process::id()));
+    std::fs::write(&file, b"").unwrap();
+    let cache = WitnessCache::new(1, file.join("cache").to_string_lossy().into_owned());
+    let block_traces = [block_trace];
+    assert!(!cache.row_usage(&block_traces).unwrap().is_empty());
+    assert!(std::sync::Arc::ptr_eq(
+        &cache.witness_block(&block_traces).unwrap(),
+        &cache.witness_block(&block_traces).unwrap()
+    ));
+    std::fs::remove_file(file).unwrap();
+}
+
+#[test]
+fn test_minimize() {
+    use zkevm::minimizer::minimize;
+
+    init();
+
+    let (_, block_traces) = load_batch_traces("./tests/traces/kroma");
+    let numbers = |traces: &[types::eth::BlockTrace]| {
+        traces
+            .iter()
+            .map(|t| t.header.number.unwrap())
+            .collect::<Vec<_>>()
+    };
+
+    // blocks which do not matter are removed
+    let wanted = block_traces[1].header.number;
+    let minimized = minimize(block_traces.clone(), |candidate| {
+        candidate.iter().any(|t| t.header.number == wanted)
+    });
+    assert_eq!(numbers(&minimized), vec![wanted.unwrap()]);
+
+    // txs are removed in chunks down to the one which matters, with their execution results
+    let block_trace = block_traces
+        .iter()
+        .find(|t| t.transactions.len() > 2)
+        .unwrap()
+        .clone();
+    let wanted = block_trace.transactions[2].tx_hash;
+    let mut attempts = 0;
+    let minimized = minimize(vec![block_trace.clone()], |candidate| {
+        attempts += 1;
+        candidate[0]
+            .transactions
+            .iter()
+            .any(|tx| tx.tx_hash == wanted)
+    });
+    assert_eq!(minimized[0].transactions.len(), 1);
+    assert_eq!(minimized[0].transactions[0].tx_hash, wanted);
+    assert_eq!(minimized[0].execution_results.len(), 1);
+    assert!(attempts < 2 * block_trace.transactions.len());
+
+    // once no tx is left, only the proof of the coinbase is used
+    let minimized = minimize(vec![block_trace.clone()], |_| true);
+    assert!(minimized[0].transactions.is_empty());
+    let proofs = minimized[0]
+        .storage_trace
+        .proofs
+        .clone()
+        .unwrap_or_default();
+    assert!(proofs
+        .keys()
+        .all(|address| Some(*address) == block_trace.coinbase.address));
+    assert!(minimized[0]
+        .storage_trace
+        .storage_proofs
+        .values()
+        .all(|slots| slots.is_empty()));
+
+    // nothing is removed unless the failure reproduces
+    let minimized = minimize(block_traces.clone(), |_| false);
+    assert_eq!(numbers(&minimized), numbers(&block_traces));
+    assert_eq!(
+        serde_json::to_value(&minimized).unwrap(),
+        serde_json::to_value(&block_traces).unwrap()
+    );
+}
+
+#[test]
+fn test_mock_prove_report() {
+    use halo2_proofs::dev::{FailureLocation, VerifyFailure};
+    use halo2_proofs::plonk::Any;
+    use zkevm::circuit::WITNESS_CACHE;
+    use zkevm::mock_report::{step_offsets, MockProveReport};
+
+    init();
+
+    let (_, block_traces) = load_batch_traces("./tests/traces/kroma");
+    let witness_block = WITNESS_CACHE.witness_block(&block_traces).unwrap();
+    let offsets = step_offsets(&witness_block);
+    // every tx has at least the begin and end tx steps
+    let (tx_index, step_index) = (witness_block.txs.len() - 1, 0);
+    let failure_at = |offset| VerifyFailure::Permutation {
+        column: (Any::Fixed, 0).into(),
+        location: FailureLocation::InRegion {
+            region: (0, "Execution step").into(),
+            offset,
+        },
+    };
+    let failures = vec![
+        failure_at(offsets[tx_index][step_index]),
+        failure_at(offsets[tx_index][step_index + 1] - 1),
+    ];
+
+    let report = MockProveReport::new("super", &witness_block, vec![], &failures);
+    assert_eq!(report.failure_count, 2);
+    assert_eq!(report.groups.len(), 1);
+    let group = &report.groups[0];
+    assert_eq!((group.sub_circuit.as_str(), group.count), ("evm", 2));
+    let step = &witness_block.txs[tx_index].steps[step_index];
+    for failure in &group.failures {
+        let located = failure.step.as_ref().unwrap();
+        assert_eq!(
+            (located.tx_index, located.step_index),
+            (tx_index, step_index)
+        );
+        assert_eq!(located.pc, step.program_counter);
+    }
+}
+
+#[cfg(feature = "zktrie")]
+#[test]
+fn test_zktrie_rows() {
+    use zkevm::circuit::{check_capacity, usable_rows, STATE_ROOT_PROVING, SUB_CIRCUIT_NAMES};
+
+    init();
+
+    assert!(STATE_ROOT_PROVING);
+    let mpt = SUB_CIRCUIT_NAMES.iter().position(|n| *n == "mpt").unwrap();
+    let (names, block_traces) = load_batch_traces("./tests/traces/kroma");
+    for (name, block_trace) in names.iter().zip(&block_traces) {
+        let check = check_capacity(block_trace, &[], usable_rows()).unwrap();
+        assert!(check.trace_usage[mpt] > 0, "{name} has no mpt rows");
+    }
+}
+
+#[test]
+fn test_resolve_chain_id() {
+    use ethers_core::types::U256;
+    use zkevm::circuit::{block_traces_to_witness_block, resolve_chain_id, ChainIdError, CHAIN_ID};
+    use zkevm::utils::get_block_trace_from_file;
+
+    init();
+
+    let chain_id = U256::from(901);
+    let deposit_only = get_block_trace_from_file("./tests/traces/kroma/user_deposit.json");
+    assert!(deposit_only.transactions.iter().all(|tx| tx.type_ == 126));
+    assert_eq!(
+        resolve_chain_id(std::slice::from_ref(&deposit_only)),
+        Ok(chain_id)
+    );
+    block_traces_to_witness_block(std::slice::from_ref(&deposit_only)).unwrap();
+
+    // no global fallback for traces which tell no chain
+    let mut missing = deposit_only.clone();
+    missing.chain_id = U256::zero();
+    assert_eq!(
+        resolve_chain_id(std::slice::from_ref(&missing)),
+        Err(ChainIdError::Missing)
+    );
+    assert!(block_traces_to_witness_block(&[missing]).is_err());
+    assert_eq!(resolve_chain_id(&[]), Ok((*CHAIN_ID).into()));
+
+    let (_, block_traces) = load_batch_traces("./tests/traces/kroma");
+    assert_eq!(resolve_chain_id(&block_traces), Ok(chain_id));
+
+    let mut mixed = get_block_trace_from_file("./tests/traces/kroma/multiple_transfers_0.json");
+    mixed.transactions[1].chain_id = U256::from(902);
+    assert!(matches!(
+        resolve_chain_id(&[mixed]),
+        Err(ChainIdError::TxMismatch { tx_index: 1, .. })
+    ));
+
+    let mut other_chain = deposit_only.clone();
+    other_chain.chain_id = U256::from(902);
+    assert!(matches!(
+        resolve_chain_id(&[deposit_only, other_chain]),
+        Err(ChainIdError::BlockMismatch { .. })
+    ));
+}
+
+#[test]
+fn test_deposit_txs() {
+    use types::eth::{DepositTxError, KromaDepositTx};
+    use zkevm::circuit::block_traces_to_witness_block;
+
+    init();
+
+    let (names, block_traces) = load_batch_traces("./tests/traces/kroma");
+    for (name, block_trace) in names.iter().zip(&block_traces) {
+        let mut deposits = 0;
+        for (tx, execution_result) in block_trace
+            .transactions
+            .iter()
+            .zip(&block_trace.execution_results)
+        {
+            match KromaDepositTx::try_from(tx) {
+                Ok(deposit) => {
+                    deposits += 1;
+                    let nonce = execution_result.from.as_ref().unwrap().nonce.unwrap();
+                    assert_eq!(deposit.check_nonce(nonce, execution_result), Ok(nonce));
+                    assert!(matches!(
+                        deposit.check_nonce(nonce + 1, execution_result),
+                        Err(DepositTxError::NonceMismatch { .. })
+                    ));
+                    let eth_tx = deposit.to_eth_tx(nonce, None, None, None);
+                    assert_eq!(eth_tx.nonce, nonce.into());
+                    assert_eq!(eth_tx.from, tx.from);
+                }
+                Err(e) => assert!(matches!(e, DepositTxError::NotDeposit { .. }), "{e}"),
             }
+        }
+        assert!(deposits > 0, "{name} has no deposit tx");
+        // mixed blocks execute the deposits with the nonces of the state
+        block_traces_to_witness_block(std::slice::from_ref(block_trace)).unwrap();
+    }
+
+    let mut block_trace = block_traces[0].clone();
+    block_trace.transactions[0].mint = None;
+    assert!(matches!(
+        KromaDepositTx::try_from(&block_trace.transactions[0]),
+        Err(DepositTxError::MissingMint(_))
+    ));
+    // the witness fails with the error, rather than panicking
+    let err = block_traces_to_witness_block(&[block_trace]).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<DepositTxError>(),
+        Some(DepositTxError::MissingMint(_))
+    ));
+
+    let mut block_trace = block_traces[0].clone();
+    let from = block_trace.execution_results[0].from.as_mut().unwrap();
+    from.nonce = Some(from.nonce.unwrap() + 1);
+    let err = block_traces_to_witness_block(&[block_trace]).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<DepositTxError>(),
+        Some(DepositTxError::NonceMismatch { .. })
+    ));
+}
+
+#[test]
+fn test_effective_gas_price() {
+    use ethers_core::types::U256;
+    use types::eth::FeeError;
+    use zkevm::utils::get_block_trace_from_file;
+
+    init();
+
+    let block_trace =
+        get_block_trace_from_file("./tests/traces/kroma/type2_gasprice_accesslist.json");
+    let base_fee = block_trace.header.base_fee_per_gas;
+    for tx in &block_trace.transactions {
+        let expected = if tx.mint.is_some() {
+            U256::zero()
+        } else {
+            tx.gas_price
         };
-        log::info!("{} circuit: {:?}", circuit, rows);
+        assert_eq!(tx.effective_gas_price(base_fee), Ok(expected));
+    }
+
+    let mut tx = block_trace.transactions[1].clone();
+    assert_eq!(tx.type_, 2);
+    assert!(tx.access_list.is_some());
+    let tip = tx.gas_price - base_fee.unwrap();
+    tx.max_fee_per_gas = Some(tx.gas_price * 2);
+    tx.max_priority_fee_per_gas = Some(tip);
+    assert_eq!(tx.effective_gas_price(base_fee), Ok(tx.gas_price));
+
+    // the fee caps survive the conversion
+    let json = serde_json::to_string(&tx).unwrap();
+    assert!(json.contains("maxFeePerGas"));
+    let eth_tx = serde_json::from_str::<types::eth::TransactionTrace>(&json)
+        .unwrap()
+        .to_eth_tx(None, None, None)
+        .unwrap();
+    assert_eq!(eth_tx.max_fee_per_gas, tx.max_fee_per_gas);
+    assert_eq!(eth_tx.max_priority_fee_per_gas, tx.max_priority_fee_per_gas);
+    assert_eq!(eth_tx.access_list, tx.access_list);
+
+    tx.max_priority_fee_per_gas = Some(tip + 1);
+    assert!(matches!(
+        tx.effective_gas_price(base_fee),
+        Err(FeeError::GasPriceMismatch { .. })
+    ));
+    tx.max_fee_per_gas = Some(base_fee.unwrap() - 1);
+    assert!(matches!(
+        tx.effective_gas_price(base_fee),
+        Err(FeeError::FeeCapBelowBaseFee { .. })
+    ));
+
+    let mut tx = block_trace.transactions[1].clone();
+    assert!(matches!(
+        tx.effective_gas_price(Some(tx.gas_price + 1)),
+        Err(FeeError::GasPriceBelowBaseFee { .. })
+    ));
+    assert_eq!(
+        tx.effective_gas_price(None),
+        Err(FeeError::MissingBaseFee(tx.tx_hash))
+    );
+    // legacy txs pay their gas price whatever the base fee
+    tx.type_ = 0;
+    assert_eq!(tx.effective_gas_price(None), Ok(tx.gas_price));
+}
+
+#[test]
+fn test_history_hashes() {
+    use eth_types::evm_types::OpcodeId;
+    use ethers_core::types::{H256, U256};
+    use types::compact;
+    use types::eth::{validate, TraceIssueKind, MAX_HISTORY_HASHES};
+    use zkevm::circuit::block_traces_to_witness_block;
+
+    init();
+
+    let (_, block_traces) = load_batch_traces("./tests/traces/kroma");
+    let mut block_trace = block_traces[0].clone();
+    let number = block_trace.header.number.unwrap().as_u64();
+    let first = number.saturating_sub(MAX_HISTORY_HASHES as u64);
+    block_trace.history_hashes = (first..number - 1)
+        .map(H256::from_low_u64_be)
+        .chain(std::iter::once(block_trace.header.parent_hash))
+        .collect();
+
+    assert_eq!(
+        block_trace.block_hash(U256::from(number - 1)),
+        Some(block_trace.header.parent_hash)
+    );
+    if number > 1 {
+        assert_eq!(
+            block_trace.block_hash(U256::from(number - 2)),
+            Some(H256::from_low_u64_be(number - 2))
+        );
     }
+    // out of range blocks hash to zero
+    assert_eq!(
+        block_trace.block_hash(U256::from(number)),
+        Some(H256::zero())
+    );
+
+    let buf = compact::encode_block_trace(&block_trace).unwrap();
+    let decoded = compact::decode_block_trace(&buf).unwrap();
+    assert_eq!(decoded.history_hashes, block_trace.history_hashes);
+
+    assert!(validate(&block_trace).is_empty());
+    block_traces_to_witness_block(std::slice::from_ref(&block_trace)).unwrap();
+
+    // a BLOCKHASH of the parent needs the parent hash
+    let mut step = block_trace.execution_results[0].exec_steps[0].clone();
+    step.op = OpcodeId::BLOCKHASH;
+    step.stack = Some(vec![U256::from(number - 1)]);
+    block_trace.execution_results[0].exec_steps.push(step);
+    assert!(validate(&block_trace).is_empty());
+    block_trace.history_hashes.clear();
+    let kinds: Vec<_> = validate(&block_trace).into_iter().map(|i| i.kind).collect();
+    assert_eq!(kinds, vec![TraceIssueKind::MissingHistoryHash]);
+
+    block_trace.history_hashes = vec![H256::zero()];
+    let kinds: Vec<_> = validate(&block_trace).into_iter().map(|i| i.kind).collect();
+    assert!(kinds.contains(&TraceIssueKind::InvalidHistoryHashes));
+}
+
+#[test]
+fn test_validate_traces() {
+    use types::eth::{validate, TraceIssueKind};
+
+    init();
+
+    let (names, block_traces) = load_batch_traces("./tests/traces");
+    for (name, block_trace) in names.iter().zip(&block_traces) {
+        let issues = validate(block_trace);
+        assert!(issues.is_empty(), "{name}: {issues:?}");
+    }
+
+    let (_, block_traces) = load_batch_traces("./tests/traces/kroma");
+    let mut block_trace = block_traces
+        .into_iter()
+        .find(|t| t.transactions.iter().any(|tx| tx.mint.is_some()))
+        .unwrap();
+    let deposit = block_trace
+        .transactions
+        .iter()
+        .position(|tx| tx.mint.is_some())
+        .unwrap();
+    block_trace.transactions[deposit].mint = None;
+    block_trace.execution_results.pop();
+
+    let kinds: Vec<_> = validate(&block_trace).into_iter().map(|i| i.kind).collect();
+    assert!(kinds.contains(&TraceIssueKind::ExecutionResultCount));
+    assert!(kinds.contains(&TraceIssueKind::MissingDepositField));
 }
 
 #[cfg(feature = "prove_verify")]
 #[test]
 fn test_mock_prove() {
-    use zkevm::circuit;
+    use zkevm::circuit::CIRCUIT_REGISTRY;
 
     use crate::test_util::load_block_traces_for_test;
 
@@ -75,47 +863,23 @@ fn test_mock_prove() {
     let block_traces = load_block_traces_for_test().1;
 
     for circuit in CIRCUIT.split(",") {
-        match circuit {
-            "evm" => Prover::mock_prove_target_circuit_batch::<circuit::EvmCircuit>(&block_traces)
-                .unwrap(),
-            "state" => {
-                Prover::mock_prove_target_circuit_batch::<circuit::StateCircuit>(&block_traces)
-                    .unwrap()
-            }
-            "zktrie" => {
-                Prover::mock_prove_target_circuit_batch::<circuit::ZktrieCircuit>(&block_traces)
-                    .unwrap()
-            }
-            "poseidon" => {
-                Prover::mock_prove_target_circuit_batch::<circuit::PoseidonCircuit>(&block_traces)
-                    .unwrap()
-            }
-            "super" => {
-                Prover::mock_prove_target_circuit_batch::<circuit::SuperCircuit>(&block_traces)
-                    .unwrap()
-            }
-            _ => {
-                log::error!("invalid circuit, skip: {:?}", circuit);
-            }
-        };
+        match CIRCUIT_REGISTRY.get(circuit) {
+            Some(entry) => entry.mock_prove(&block_traces).unwrap(),
+            None => log::error!("invalid circuit, skip: {:?}", circuit),
+        }
     }
 }
 
 #[cfg(feature = "prove_verify")]
 #[test]
 fn test_prove_verify() {
-    use zkevm::circuit;
+    use zkevm::circuit::CIRCUIT_REGISTRY;
+
     for circuit in CIRCUIT.split(",") {
-        match circuit {
-            "evm" => test_target_circuit_prove_verify::<circuit::EvmCircuit>(),
-            "state" => test_target_circuit_prove_verify::<circuit::StateCircuit>(),
-            "zktrie" => test_target_circuit_prove_verify::<circuit::ZktrieCircuit>(),
-            "poseidon" => test_target_circuit_prove_verify::<circuit::PoseidonCircuit>(),
-            "super" => test_target_circuit_prove_verify::<circuit::SuperCircuit>(),
-            _ => {
-                log::error!("invalid circuit, skip: {:?}", circuit);
-            }
-        };
+        match CIRCUIT_REGISTRY.get(circuit) {
+            Some(entry) => test_target_circuit_prove_verify(entry),
+            None => log::error!("invalid circuit, skip: {:?}", circuit),
+        }
     }
 }
 
@@ -162,7 +926,38 @@ fn test_vk_same() {
     assert_eq!(vk_empty_bytes, vk_real_bytes);
 }
 
-fn test_target_circuit_prove_verify<C: TargetCircuit>() {
+#[cfg(feature = "prove_verify")]
+#[test]
+fn test_resume() {
+    use zkevm::checkpoint::{checkpoint_key, CheckpointStore, Stage};
+    use zkevm::verifier::Verifier;
+
+    init();
+
+    let (_, block_traces) = load_block_traces_for_test();
+    let dir = std::env::temp_dir().join(format!("kroma_resume_{}", std::process::id()));
+    let store = CheckpointStore::new(&dir).unwrap();
+    let key = checkpoint_key(&block_traces);
+    let mut prover = Prover::from_fpath(PARAMS_DIR, SEED_PATH);
+    let mut verifier = Verifier::from_fpath(PARAMS_DIR, None);
+
+    let proof = prover.resume(&block_traces, &store, &mut verifier).unwrap();
+    assert!(store.path(&key, Stage::Target).exists());
+    assert!(store.path(&key, Stage::Agg).exists());
+
+    // the agg proof is reused as is
+    let resumed = prover.resume(&block_traces, &store, &mut verifier).unwrap();
+    assert_eq!(resumed.proof, proof.proof);
+
+    // a lost agg proof is redone from the target proof
+    store.remove(&key, Stage::Agg);
+    let resumed = prover.resume(&block_traces, &store, &mut verifier).unwrap();
+    assert!(verifier.verify_agg_circuit_proof(resumed).is_ok());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+fn test_target_circuit_prove_verify(circuit: &zkevm::circuit::CircuitEntry) {
     use std::time::Instant;
 
     use zkevm::verifier::Verifier;
@@ -171,17 +966,15 @@ fn test_target_circuit_prove_verify<C: TargetCircuit>() {
 
     let (_, block_traces) = load_block_traces_for_test();
 
-    log::info!("start generating {} proof", C::name());
+    log::info!("start generating {} proof", circuit.name);
     let now = Instant::now();
     let mut prover = Prover::from_fpath(PARAMS_DIR, SEED_PATH);
-    let proof = prover
-        .create_target_circuit_proof_batch::<C>(&block_traces)
-        .unwrap();
+    let proof = circuit.prove(&mut prover, &block_traces).unwrap();
     log::info!("finish generating proof, elapsed: {:?}", now.elapsed());
 
     let output_file = format!(
         "/tmp/{}_{}.json",
-        C::name(),
+        circuit.name,
         Utc::now().format("%Y%m%d_%H%M%S")
     );
     let mut fd = std::fs::File::create(&output_file).unwrap();
@@ -191,6 +984,6 @@ fn test_target_circuit_prove_verify<C: TargetCircuit>() {
     log::info!("start verifying proof");
     let now = Instant::now();
     let mut verifier = Verifier::from_fpath(PARAMS_DIR, None);
-    assert!(verifier.verify_target_circuit_proof::<C>(&proof).is_ok());
+    assert!(circuit.verify(&mut verifier, &proof).is_ok());
     log::info!("finish verifying proof, elapsed: {:?}", now.elapsed());
 }
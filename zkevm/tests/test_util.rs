@@ -2,6 +2,7 @@ use git_version::git_version;
 use glob::glob;
 use std::sync::Once;
 use types::eth::BlockTrace;
+use zkevm::fixture::{FixtureManifest, MANIFEST_FILE};
 use zkevm::utils::get_block_trace_from_file;
 use zkevm::utils::read_env_var;
 
@@ -20,10 +21,20 @@ pub fn init() {
 }
 
 pub fn load_batch_traces(batch_dir: &str) -> (Vec<String>, Vec<types::eth::BlockTrace>) {
-    let file_names: Vec<String> = glob(&format!("{batch_dir}/**/*.json"))
-        .unwrap()
-        .map(|p| p.unwrap().to_str().unwrap().to_string())
-        .collect();
+    // recorded fixtures are listed in the manifest, anything else is picked up by name
+    let file_names: Vec<String> = match FixtureManifest::load(batch_dir).unwrap() {
+        Some(manifest) => manifest
+            .paths(batch_dir)
+            .into_iter()
+            .map(|p| p.to_str().unwrap().to_string())
+            .collect(),
+        None => glob(&format!("{batch_dir}/**/*.json"))
+            .unwrap()
+            .map(|p| p.unwrap())
+            .filter(|p| p.file_name().unwrap() != MANIFEST_FILE)
+            .map(|p| p.to_str().unwrap().to_string())
+            .collect(),
+    };
     log::info!("test batch with {:?}", file_names);
     let mut names_and_traces = file_names
         .into_iter()